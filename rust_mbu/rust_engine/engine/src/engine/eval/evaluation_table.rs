@@ -1,9 +1,11 @@
 use std::sync::atomic::{AtomicI32, AtomicU64, Ordering};
 
+use sdk::zeroed::ZeroedTable;
+
 pub type TableEntry = (AtomicU64, AtomicI32);
 
 pub struct EvaluationTable {
-    pub table: Vec<TableEntry>,
+    pub table: ZeroedTable<TableEntry>,
     pub size: usize,
 }
 
@@ -14,13 +16,20 @@ impl Default for EvaluationTable {
 }
 
 impl EvaluationTable {
+    #[must_use]
     pub fn new(size_in_mb: usize) -> Self {
         let size = size_in_mb * 1024 * 1024 / std::mem::size_of::<TableEntry>();
-        let mut table = Vec::with_capacity(size);
-        for _ in 0..size {
-            table.push((AtomicU64::new(0), AtomicI32::new(0)));
+        EvaluationTable {
+            table: ZeroedTable::new(size),
+            size,
+        }
+    }
+
+    pub fn clear(&self) {
+        for (hash, value) in self.table.iter() {
+            hash.store(0, Ordering::Relaxed);
+            value.store(0, Ordering::Relaxed);
         }
-        EvaluationTable { table, size }
     }
 
     pub fn write(&self, hash: u64, value: i32) {
@@ -29,6 +38,7 @@ impl EvaluationTable {
         self.table[index].1.store(value, Ordering::Relaxed);
     }
 
+    #[must_use]
     pub fn read(&self, hash: u64) -> Option<i32> {
         let index = (hash % self.size as u64) as usize;
         let entry = &self.table[index];
@@ -52,5 +62,8 @@ mod tests {
         assert_eq!(table.read(1), None);
         table.write(0, 11);
         assert_eq!(table.read(0), Some(11));
+        table.write(5, 12);
+        table.clear();
+        assert_eq!(table.read(5), None);
     }
 }
@@ -1,6 +1,7 @@
 pub mod activity;
 pub mod evaluation_table;
 pub mod king_safety;
+pub mod pawn_table;
 pub mod pawns;
 pub mod pin_bonus;
 pub mod positional_tables;
@@ -17,27 +18,25 @@ pub trait Evaluate {
 use self::{
     activity::bonus_for_mobility,
     evaluation_table::EvaluationTable,
-    king_safety::{bonus_for_pieces_close_to_king, calc_king_safety},
-    pawns::{
-        isolated::isolated_pawns,
-        protected_passed_pawnes::passed_pawns,
-        stacked::stacked_pawns,
-        strong_squares::{
-            bonus as bonus_for_strong_squares, bonus_for_piece as bonus_for_piece_on_strong_squares,
-        },
-    },
+    king_safety::calc_king_safety,
+    pawn_table::PawnTable,
+    pawns::strong_squares::bonus_for_piece_on as bonus_for_piece_on_strong_squares,
     pin_bonus::bonus_for_absolute_pins,
     positional_tables::{game_phase, tapered_eval},
     rooks::{
         battery::bonus_for_rook_batteries,
-        rook_on_open_files::{bonus_rook_for_open_files, bonus_rook_for_semi_open_files},
+        rook_on_open_files::{bonus_rook_for_open_files_on, bonus_rook_for_semi_open_files_on},
     },
 };
 
 pub const PIECE_VALUES: [i32; 6] = [100, 300, 320, 500, 900, 10000];
 
 #[must_use]
-pub fn evaluate(position: &Position, eval_table: &Arc<EvaluationTable>) -> i32 {
+pub fn evaluate(
+    position: &Position,
+    eval_table: &Arc<EvaluationTable>,
+    pawn_table: &Arc<PawnTable>,
+) -> i32 {
     if let Some(value) = eval_table.read(position.hash) {
         return value;
     }
@@ -53,14 +52,21 @@ pub fn evaluate(position: &Position, eval_table: &Arc<EvaluationTable>) -> i32 {
     //let score = material(position);
     //let phase_factor = f64::from(phase) / 24.0;
 
+    let pawns = pawn_table.probe(position);
+
     score += calc_king_safety(position);
-    score += isolated_pawns(position);
-    score += stacked_pawns(position);
-    score += passed_pawns(position);
-    score += bonus_for_strong_squares(position);
-    score += bonus_for_piece_on_strong_squares(position);
-    score += bonus_rook_for_open_files(position);
-    score += bonus_rook_for_semi_open_files(position);
+    score += pawns.score(phase);
+    score += bonus_for_piece_on_strong_squares(
+        position,
+        pawns.strong_squares[Color::White as usize],
+        pawns.strong_squares[Color::Black as usize],
+    );
+    score += bonus_rook_for_open_files_on(position, pawns.open_files);
+    score += bonus_rook_for_semi_open_files_on(
+        position,
+        pawns.semi_open_files[Color::White as usize],
+        pawns.semi_open_files[Color::Black as usize],
+    );
     score += bonus_for_rook_batteries(position);
     score += bonus_for_absolute_pins(position);
     score += bonus_for_mobility(position);
@@ -0,0 +1,231 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use sdk::{
+    bitboard::Bitboard,
+    position::{Color, Position},
+    zeroed::ZeroedTable,
+};
+
+use super::pawns::{
+    isolated::isolated_pawns,
+    protected_passed_pawnes::{mask_passed_pawns, passed_pawns},
+    stacked::stacked_pawns,
+    strong_squares::{bonus as bonus_for_strong_squares, strong_squares},
+};
+
+/// Terms of the evaluation that depend only on the pawns of both sides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PawnEntry {
+    pub middlegame_score: i32,
+    pub endgame_score: i32,
+    /// Indexed by `Color as usize`.
+    pub passed_pawns: [Bitboard; 2],
+    pub strong_squares: [Bitboard; 2],
+    pub semi_open_files: [Bitboard; 2],
+    pub open_files: Bitboard,
+}
+
+/// Number of `u64` words of a stored [`PawnEntry`], without the key.
+const DATA_WORDS: usize = 8;
+
+/// Stored key, XOR-ed with the data so that torn writes from other threads read as a miss.
+pub type TableEntry = (AtomicU64, [AtomicU64; DATA_WORDS]);
+
+/// Mixed into stored keys, so that an empty slot isn't a valid entry for the pawnless hash 0.
+const KEY_SALT: u64 = 0x9E37_79B9_7F4A_7C15;
+
+pub struct PawnTable {
+    pub table: ZeroedTable<TableEntry>,
+    pub size: usize,
+    /// Hits and probes are only counted with the `Debug` option, as the shared counters would
+    /// slow down the evaluation in every search thread.
+    count_stats: AtomicBool,
+    hits: AtomicU64,
+    probes: AtomicU64,
+}
+
+impl PawnEntry {
+    #[must_use]
+    pub fn new(position: &Position) -> Self {
+        let score = isolated_pawns(position)
+            + stacked_pawns(position)
+            + passed_pawns(position)
+            + bonus_for_strong_squares(position);
+
+        PawnEntry {
+            middlegame_score: score,
+            endgame_score: score,
+            passed_pawns: [
+                mask_passed_pawns(position, Color::White),
+                mask_passed_pawns(position, Color::Black),
+            ],
+            strong_squares: [
+                strong_squares(position, Color::White),
+                strong_squares(position, Color::Black),
+            ],
+            semi_open_files: [
+                position.semi_open_files(&Color::White),
+                position.semi_open_files(&Color::Black),
+            ],
+            open_files: position.open_files(),
+        }
+    }
+
+    /// Pawn score from white's perspective, tapered the same way as `tapered_eval`.
+    #[must_use]
+    pub fn score(&self, phase: i32) -> i32 {
+        let middlegame_phase = i32::min(phase, 24);
+        let endgame_phase = 24 - middlegame_phase;
+
+        (self.middlegame_score * middlegame_phase + self.endgame_score * endgame_phase) / 24
+    }
+
+    fn to_words(self) -> [u64; DATA_WORDS] {
+        [
+            u64::from(self.middlegame_score as u32) | (u64::from(self.endgame_score as u32) << 32),
+            self.passed_pawns[0].0,
+            self.passed_pawns[1].0,
+            self.strong_squares[0].0,
+            self.strong_squares[1].0,
+            self.semi_open_files[0].0,
+            self.semi_open_files[1].0,
+            self.open_files.0,
+        ]
+    }
+
+    fn from_words(words: [u64; DATA_WORDS]) -> Self {
+        PawnEntry {
+            middlegame_score: words[0] as u32 as i32,
+            endgame_score: (words[0] >> 32) as u32 as i32,
+            passed_pawns: [Bitboard(words[1]), Bitboard(words[2])],
+            strong_squares: [Bitboard(words[3]), Bitboard(words[4])],
+            semi_open_files: [Bitboard(words[5]), Bitboard(words[6])],
+            open_files: Bitboard(words[7]),
+        }
+    }
+}
+
+impl Default for PawnTable {
+    fn default() -> Self {
+        Self::new(4)
+    }
+}
+
+impl PawnTable {
+    #[must_use]
+    pub fn new(size_in_mb: usize) -> Self {
+        let size = size_in_mb * 1024 * 1024 / std::mem::size_of::<TableEntry>();
+        PawnTable {
+            table: ZeroedTable::new(size),
+            size,
+            count_stats: AtomicBool::new(false),
+            hits: AtomicU64::new(0),
+            probes: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the pawn entry of the position, computing and storing it on a miss.
+    #[must_use]
+    pub fn probe(&self, position: &Position) -> PawnEntry {
+        let count_stats = self.count_stats.load(Ordering::Relaxed);
+        if count_stats {
+            self.probes.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(entry) = self.read(position.pawn_hash) {
+            if count_stats {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+            }
+            return entry;
+        }
+
+        let entry = PawnEntry::new(position);
+        self.write(position.pawn_hash, &entry);
+
+        entry
+    }
+
+    pub fn write(&self, hash: u64, entry: &PawnEntry) {
+        let index = (hash % self.size as u64) as usize;
+        let (key, data) = &self.table[index];
+        let words = entry.to_words();
+
+        for (slot, word) in data.iter().zip(words) {
+            slot.store(word, Ordering::Relaxed);
+        }
+        key.store(hash ^ KEY_SALT ^ checksum(&words), Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn read(&self, hash: u64) -> Option<PawnEntry> {
+        let index = (hash % self.size as u64) as usize;
+        let (key, data) = &self.table[index];
+        let words = std::array::from_fn(|i| data[i].load(Ordering::Relaxed));
+
+        (key.load(Ordering::Relaxed) ^ KEY_SALT ^ checksum(&words) == hash)
+            .then(|| PawnEntry::from_words(words))
+    }
+
+    pub fn clear(&self) {
+        for (key, data) in self.table.iter() {
+            key.store(0, Ordering::Relaxed);
+            for word in data {
+                word.store(0, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn set_count_stats(&self, count_stats: bool) {
+        self.count_stats.store(count_stats, Ordering::Relaxed);
+    }
+
+    /// Number of hits and probes while counting was on, `None` if it is off.
+    #[must_use]
+    pub fn stats(&self) -> Option<(u64, u64)> {
+        self.count_stats.load(Ordering::Relaxed).then(|| {
+            (
+                self.hits.load(Ordering::Relaxed),
+                self.probes.load(Ordering::Relaxed),
+            )
+        })
+    }
+}
+
+fn checksum(words: &[u64; DATA_WORDS]) -> u64 {
+    words.iter().fold(0, |acc, word| acc ^ word)
+}
+
+#[cfg(test)]
+mod tests {
+    use sdk::{fen::Fen, position::Position};
+
+    use super::*;
+
+    #[test]
+    fn test_pawn_table() {
+        let table = PawnTable::new(1);
+        let pos = Position::from_fen("4k3/p4ppp/8/1P6/8/8/5PPP/4K3 w - - 0 1".to_string()).unwrap();
+        assert_eq!(table.read(pos.pawn_hash), None);
+
+        let entry = table.probe(&pos);
+        assert_eq!(table.stats(), None);
+        table.set_count_stats(true);
+        assert_eq!(entry, PawnEntry::new(&pos));
+        assert_eq!(table.read(pos.pawn_hash), Some(entry));
+        assert_eq!(table.probe(&pos), entry);
+        assert_eq!(table.probe(&pos), entry);
+        assert_eq!(table.stats(), Some((2, 2)));
+
+        // Negative scores survive the packing.
+        let mut entry = entry;
+        entry.middlegame_score = -17;
+        entry.endgame_score = 42;
+        table.write(pos.pawn_hash, &entry);
+        assert_eq!(table.read(pos.pawn_hash), Some(entry));
+        assert_eq!(table.read(pos.pawn_hash ^ 1), None);
+        assert_eq!(table.read(0), None);
+
+        table.clear();
+        assert_eq!(table.read(pos.pawn_hash), None);
+        assert_eq!(table.read(0), None);
+    }
+}
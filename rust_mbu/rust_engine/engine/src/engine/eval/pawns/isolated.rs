@@ -51,7 +51,7 @@ mod tests {
         assert!(find_isolated_pawns(&pos, Color::White).is_empty());
 
         let pos = Position::from_fen(
-            "rnbqkbnr/pp2pPpp/1P6/3p4/8/2p5/P1PP3P/RNBQKBNR w KQkq - 0 1".to_string(),
+            "rnbqkbnr/pp2pPpp/1P6/3p4/8/2p5/P1PP3P/RNBQKBNR b KQkq - 0 1".to_string(),
         )
         .unwrap();
         assert_eq!(find_isolated_pawns(&pos, Color::White).count(), 2);
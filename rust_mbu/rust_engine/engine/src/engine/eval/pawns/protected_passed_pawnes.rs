@@ -23,7 +23,6 @@ pub fn passed_pawns(pos: &Position) -> i32 {
 #[must_use]
 pub fn mask_protected_passed_pawns(pos: &Position, color: Color) -> (u8, u8) {
     let our_pawns = pos.pieces[color as usize][Piece::Pawn as usize];
-    let enemy_pawns = pos.pieces[color.enemy() as usize][Piece::Pawn as usize];
 
     let back_dirs = if color == Color::White {
         [Direction::SouthEast, Direction::SouthWest]
@@ -31,6 +30,20 @@ pub fn mask_protected_passed_pawns(pos: &Position, color: Color) -> (u8, u8) {
         [Direction::NorthEast, Direction::NorthWest]
     };
 
+    let passed_pawns = mask_passed_pawns(pos, color);
+    let protected_pawns = our_pawns.shift(&back_dirs[0]) | our_pawns.shift(&back_dirs[1]);
+
+    let passed_pawns_not_protected = (passed_pawns & !protected_pawns).count();
+    let passed_pawns_protected = (passed_pawns & protected_pawns).count();
+
+    (passed_pawns_not_protected, passed_pawns_protected)
+}
+
+#[must_use]
+pub fn mask_passed_pawns(pos: &Position, color: Color) -> Bitboard {
+    let our_pawns = pos.pieces[color as usize][Piece::Pawn as usize];
+    let enemy_pawns = pos.pieces[color.enemy() as usize][Piece::Pawn as usize];
+
     let mut passed_pawns = Bitboard::empty();
     for pawn in our_pawns {
         let front = MOVE_GEN.lookups.passers_bb[color as usize][pawn as usize];
@@ -40,12 +53,7 @@ pub fn mask_protected_passed_pawns(pos: &Position, color: Color) -> (u8, u8) {
         }
     }
 
-    let protected_pawns = our_pawns.shift(&back_dirs[0]) | our_pawns.shift(&back_dirs[1]);
-
-    let passed_pawns_not_protected = (passed_pawns & !protected_pawns).count();
-    let passed_pawns_protected = (passed_pawns & protected_pawns).count();
-
-    (passed_pawns_not_protected, passed_pawns_protected)
+    passed_pawns
 }
 
 #[cfg(test)]
@@ -17,9 +17,20 @@ pub fn bonus(pos: &Position) -> i32 {
 
 #[must_use]
 pub fn bonus_for_piece(pos: &Position) -> i32 {
-    let white_strong_squares = strong_squares(pos, Color::White);
-    let black_strong_squares = strong_squares(pos, Color::Black);
+    bonus_for_piece_on(
+        pos,
+        strong_squares(pos, Color::White),
+        strong_squares(pos, Color::Black),
+    )
+}
 
+/// Like [`bonus_for_piece`], with the strong squares already known.
+#[must_use]
+pub fn bonus_for_piece_on(
+    pos: &Position,
+    white_strong_squares: Bitboard,
+    black_strong_squares: Bitboard,
+) -> i32 {
     let mut bonus = 0;
 
     let white_minor_pieces = pos.pieces[Color::White as usize][Piece::Knight as usize]
@@ -91,7 +91,7 @@ pub fn game_phase(position: &Position) -> i32 {
 }
 #[must_use]
 const fn flip(sq: usize) -> usize {
-    sq ^ 56
+    sq ^ 0b11_1000
 }
 
 static PIECE_PHASE_VALUES: [[i32; 6]; 2] = [
@@ -1,15 +1,21 @@
-use sdk::position::{Color, Piece, Position};
+use sdk::{
+    bitboard::Bitboard,
+    position::{Color, Piece, Position},
+};
 
 pub const BONUS_ROOK_OPEN_FILE: i32 = 30;
 pub const BONUS_ROOK_SEMI_OPEN_FILE: i32 = 18;
 
 #[must_use]
 pub fn bonus_rook_for_open_files(position: &Position) -> i32 {
+    bonus_rook_for_open_files_on(position, position.open_files())
+}
+
+#[must_use]
+pub fn bonus_rook_for_open_files_on(position: &Position, open_files: Bitboard) -> i32 {
     let white_rooks = position.pieces[Color::White as usize][Piece::Rook as usize];
     let black_rooks = position.pieces[Color::Black as usize][Piece::Rook as usize];
 
-    let open_files = position.open_files();
-
     let white_rooks_on_open_files = (white_rooks & open_files).count();
     let black_rooks_on_open_files = (black_rooks & open_files).count();
 
@@ -19,12 +25,22 @@ pub fn bonus_rook_for_open_files(position: &Position) -> i32 {
 
 #[must_use]
 pub fn bonus_rook_for_semi_open_files(position: &Position) -> i32 {
+    bonus_rook_for_semi_open_files_on(
+        position,
+        position.semi_open_files(&Color::White),
+        position.semi_open_files(&Color::Black),
+    )
+}
+
+#[must_use]
+pub fn bonus_rook_for_semi_open_files_on(
+    position: &Position,
+    semi_open_files_white: Bitboard,
+    semi_open_files_black: Bitboard,
+) -> i32 {
     let white_rooks = position.pieces[Color::White as usize][Piece::Rook as usize];
     let black_rooks = position.pieces[Color::Black as usize][Piece::Rook as usize];
 
-    let semi_open_files_white = position.semi_open_files(&Color::White);
-    let semi_open_files_black = position.semi_open_files(&Color::Black);
-
     let white_rooks_on_semi_open_files = (white_rooks & semi_open_files_white).count();
     let black_rooks_on_semi_open_files = (black_rooks & semi_open_files_black).count();
 
@@ -53,7 +69,7 @@ mod tests {
         )
         .unwrap();
 
-        assert_eq!(bonus_rook_for_open_files(&pos), BONUS_ROOK_OPEN_FILE * 1);
+        assert_eq!(bonus_rook_for_open_files(&pos), BONUS_ROOK_OPEN_FILE);
     }
 
     #[test]
@@ -1,10 +1,13 @@
 use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
     sync::{
         atomic::Ordering,
-        mpsc::{channel, Sender},
-        Arc,
+        mpsc::{channel, RecvTimeoutError, Sender},
+        Arc, LazyLock,
     },
-    thread,
+    thread::{self, JoinHandle},
+    time::Duration,
 };
 
 use crate::{
@@ -28,7 +31,15 @@ use crate::{
     },
     uci::{commands::Command, Result},
 };
-use move_gen::{generators::movegen::MoveGen, r#move::MakeMove};
+use move_gen::{
+    generators::movegen::{MoveGen, MAX_MOVES},
+    r#move::{MakeMove, Move},
+    utils::{
+        book::{Book, BookSelection},
+        chess_notation::ChessNotation,
+        perft::{divide, PerftOptions},
+    },
+};
 use sdk::{
     fen::Fen,
     position::{Color, Position},
@@ -39,18 +50,22 @@ use crate::engine::options::Options;
 use anyhow::anyhow;
 
 use self::{
-    eval::evaluation_table::EvaluationTable,
+    eval::{evaluation_table::EvaluationTable, pawn_table::PawnTable},
     search::{
         heuristics::transposition_table::TranspositionTable,
         parallel::Search,
-        utils::{repetition::Table, time_control::SearchOptions},
+        utils::{
+            repetition::Table,
+            time_control::{SearchOptions, TimeControl},
+        },
         STOPPED,
     },
 };
 
-lazy_static::lazy_static! {
-    pub static ref MOVE_GEN: MoveGen = MoveGen::default();
-}
+pub static MOVE_GEN: LazyLock<MoveGen> = LazyLock::new(MoveGen::default);
+
+/// How often the command thread checks whether the search ended while commands are queued.
+const PENDING_POLL_INTERVAL: Duration = Duration::from_millis(5);
 
 use derivative::Derivative;
 
@@ -62,10 +77,22 @@ pub mod search;
 #[derivative(Default)]
 pub struct Engine {
     pub root_pos: Position,
+    /// Start position in FEN and the moves of the last `position` command.
+    pub root: Option<(String, Vec<String>)>,
     pub repetition_table: Table,
     pub transposition_table: Arc<TranspositionTable>,
     pub evaluation_table: Arc<EvaluationTable>,
+    pub pawn_table: Arc<PawnTable>,
     pub options: Options,
+    pub book: Option<Book>,
+    /// File for `Save Hash` and `Load Hash`.
+    pub hash_file: Option<String>,
+    /// Clock of the running search, kept for `ponderhit`.
+    pub time_control: Option<Arc<TimeControl>>,
+    /// Thread of the running search, until it is joined.
+    pub search: Option<JoinHandle<()>>,
+    /// Commands which would change the state under the running search, applied once it ends.
+    pub pending: Vec<Command>,
     pub age: usize,
     #[derivative(Default(value = "true"))]
     pub ready: bool,
@@ -73,10 +100,25 @@ pub struct Engine {
 
 impl Engine {
     pub fn handle_command(&mut self, command: Command) {
+        self.join_finished_search();
+
         match command {
+            // A `go` right after `bestmove` may come before the search thread ended.
+            Command::Go(_)
+            | Command::Position(..)
+            | Command::SetOption(..)
+            | Command::UciNewGame
+                if self.is_searching() =>
+            {
+                self.pending.push(command);
+            }
+            // `readyok` is only sent once the queued commands are applied.
+            Command::IsReady if !self.pending.is_empty() => self.pending.push(command),
             Command::Uci => uci_info(),
             Command::Go(options) => self.go(options),
-            Command::Stop => self.stop(),
+            // The search is stopped before quitting as well.
+            Command::Stop | Command::Quit => self.stop(),
+            Command::PonderHit => self.ponderhit(),
             Command::Position(pos, moves) => self.position(pos, moves),
             Command::SetOption(name, value) => self.set_option(&name, value),
             Command::IsReady => println!("readyok"),
@@ -84,40 +126,101 @@ impl Engine {
             Command::UciNewGame => self.uci_new_game(),
             Command::Test => self.test(),
             Command::Simulate(moves) => self.simulate(&moves),
-            Command::Quit => {}
-        };
+        }
     }
 
+    /// Starts the thread handling commands. It ends after `quit`, once the search is stopped.
     #[must_use]
-    pub fn start_loop_thread() -> Sender<Command> {
+    pub fn start_loop_thread() -> (Sender<Command>, JoinHandle<()>) {
         let (tx, rx) = channel();
 
         let run = move || {
             let mut engine = Engine::default();
 
             loop {
-                let command = rx.recv().expect("Failed to receive command");
+                // Commands queued during a search are applied once it ends, even if no other
+                // command comes in the meantime.
+                let command = if engine.pending.is_empty() {
+                    rx.recv().expect("Failed to receive command")
+                } else {
+                    match rx.recv_timeout(PENDING_POLL_INTERVAL) {
+                        Ok(command) => command,
+                        Err(RecvTimeoutError::Timeout) => {
+                            engine.join_finished_search();
+                            continue;
+                        }
+                        Err(RecvTimeoutError::Disconnected) => panic!("Failed to receive command"),
+                    }
+                };
+                let quit = matches!(command, Command::Quit);
+
+                engine.handle_command(command);
 
-                if matches!(command, Command::Quit) {
+                if quit {
                     break;
                 }
-
-                engine.handle_command(command);
             }
         };
 
-        thread::Builder::new().spawn(run).unwrap();
+        let handle = thread::Builder::new().spawn(run).unwrap();
+
+        (tx, handle)
+    }
+
+    #[must_use]
+    pub fn is_searching(&self) -> bool {
+        self.search
+            .as_ref()
+            .is_some_and(|search| !search.is_finished())
+    }
 
-        tx
+    /// Joins the search thread if it already sent `bestmove`, and applies the commands queued
+    /// during the search.
+    fn join_finished_search(&mut self) {
+        if self.search.as_ref().is_some_and(JoinHandle::is_finished) {
+            self.join_search();
+        }
+    }
+
+    fn join_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.join().expect("Search thread panicked");
+        }
+        self.time_control = None;
+
+        for command in std::mem::take(&mut self.pending) {
+            self.handle_command(command);
+        }
     }
 
     pub fn go(&mut self, options: SearchOptions) {
+        if let Some(depth) = options.perft {
+            self.perft(depth);
+            return;
+        }
+
+        if let Some(mv) = self.book_move(&options) {
+            println!("bestmove {}", mv.to_uci(self.options.chess960));
+            return;
+        }
+
+        let search_moves = match self.search_moves(&options) {
+            Ok(moves) => moves,
+            Err(e) => {
+                println!("info string {e}");
+                return;
+            }
+        };
+
         STOPPED.store(false, Ordering::Relaxed);
         let pos = self.root_pos.clone();
-        let is_white = pos.turn == Color::White;
+        let time_control =
+            Arc::new(options.time_control(pos.turn == Color::White, self.options.move_overhead));
+        self.time_control = Some(time_control.clone());
         let rep_table = self.repetition_table.clone();
         let transposition_table = self.transposition_table.clone();
         let eval_table = self.evaluation_table.clone();
+        let pawn_table = self.pawn_table.clone();
         let engine_options = self.options;
         let age = self.age;
 
@@ -125,39 +228,167 @@ impl Engine {
             let mut search = Search::new(
                 options,
                 engine_options,
-                is_white,
+                time_control,
                 rep_table,
                 transposition_table,
                 eval_table,
+                pawn_table,
+                search_moves,
                 age,
             );
             search.search(&pos);
         };
 
-        thread::Builder::new()
+        let search = thread::Builder::new()
             .name("GoThread".to_string())
             .spawn(run)
             .unwrap();
+        self.search = Some(search);
+    }
+
+    /// Prints the leaf node count below each root move for `go perft`, on the engine's threads.
+    /// It runs like a search, so `stop` abandons it without printing the incomplete counts.
+    fn perft(&mut self, depth: usize) {
+        STOPPED.store(false, Ordering::Relaxed);
+        let pos = self.root_pos.clone();
+        let chess960 = self.options.chess960;
+        let options = PerftOptions {
+            threads: self.options.threads,
+            stop: Some(&STOPPED),
+            ..PerftOptions::default()
+        };
+
+        let run = move || {
+            let counts = divide(&MOVE_GEN, &pos, depth, &options);
+            if STOPPED.load(Ordering::Relaxed) {
+                return;
+            }
+
+            for (mv, nodes) in &counts {
+                println!("{}: {nodes}", mv.to_uci(chess960));
+            }
+
+            let nodes: usize = if depth == 0 {
+                1
+            } else {
+                counts.iter().map(|(_, nodes)| nodes).sum()
+            };
+            println!();
+            println!("Nodes searched: {nodes}");
+        };
+
+        let search = thread::Builder::new()
+            .name("PerftThread".to_string())
+            .spawn(run)
+            .unwrap();
+        self.search = Some(search);
     }
 
+    /// Legal moves of the root position given with `go searchmoves`.
+    fn search_moves(&self, options: &SearchOptions) -> Result<Vec<Move>> {
+        let legal_moves = MOVE_GEN.generate_legal_moves(&self.root_pos);
+
+        options
+            .search_moves
+            .iter()
+            .map(|mv_str| {
+                legal_moves
+                    .iter()
+                    .find(|mv| mv.to_uci(self.options.chess960) == *mv_str)
+                    .copied()
+                    .ok_or(anyhow!("Invalid move: {mv_str}"))
+            })
+            .collect()
+    }
+
+    fn book_move(&self, options: &SearchOptions) -> Option<Move> {
+        // Infinite and ponder searches must not return before `stop`.
+        if !self.options.own_book
+            || options.infinite
+            || options.ponder
+            || !options.search_moves.is_empty()
+            || usize::from(self.root_pos.fullmove_number) > self.options.book_depth
+        {
+            return None;
+        }
+
+        self.book
+            .as_ref()?
+            .choose(&MOVE_GEN, &self.root_pos, BookSelection::Weighted)
+    }
+
+    /// Stops the search and waits until it sends `bestmove`.
     pub fn stop(&mut self) {
         STOPPED.store(true, Ordering::Relaxed);
+        self.join_search();
+    }
+
+    fn save_hash(&self) {
+        let Some(path) = &self.hash_file else {
+            println!("info string Set HashFile first");
+            return;
+        };
+
+        let result = File::create(path).and_then(|file| {
+            self.transposition_table
+                .save(BufWriter::new(file), self.age)
+        });
+        match result {
+            Ok(()) => println!("info string Hash saved to {path}"),
+            Err(e) => println!("info string Couldn't save hash to {path}: {e}"),
+        }
+    }
+
+    fn load_hash(&mut self) {
+        let Some(path) = &self.hash_file else {
+            println!("info string Set HashFile first");
+            return;
+        };
+
+        let result =
+            File::open(path).and_then(|file| self.transposition_table.load(BufReader::new(file)));
+        match result {
+            Ok(age) => {
+                // Entries of the file are as fresh as they were when saved.
+                self.age = age;
+                println!("info string Hash loaded from {path}");
+            }
+            Err(e) => println!("info string Couldn't load hash from {path}: {e}"),
+        }
+    }
+
+    pub fn ponderhit(&mut self) {
+        match &self.time_control {
+            Some(time_control) => time_control.ponderhit(),
+            None => println!("info string No search to switch from pondering"),
+        }
     }
 
     fn position(&mut self, mut pos: Position, moves: Vec<String>) {
-        self.repetition_table.clear();
-        // TODO: Temp fix
-        self.transposition_table = Arc::new(TranspositionTable::new(self.options.hash));
-        self.age += 1;
-        match parse_uci_moves(moves, &mut pos) {
+        let fen = pos.to_fen();
+        match parse_uci_moves(moves.clone(), &mut pos, self.options.chess960) {
             Ok(repetition_table) => {
+                // The transposition table is always kept. If the new root doesn't continue the
+                // game of the previous one, its entries get older, so that they are replaced
+                // first.
+                if !self.continues_root(&fen, &moves) {
+                    self.age = self.age.wrapping_add(1);
+                }
+                self.root = Some((fen, moves));
                 self.root_pos = pos;
                 self.repetition_table = repetition_table;
             }
-            Err(e) => println!("{e}"),
+            Err(e) => println!("info string {e}"),
         }
     }
 
+    /// Whether the start position `fen` followed by `moves` extends the previous root.
+    fn continues_root(&self, fen: &str, moves: &[String]) -> bool {
+        self.root
+            .as_ref()
+            .is_some_and(|(root_fen, root_moves)| root_fen == fen && moves.starts_with(root_moves))
+    }
+
     fn debug(&self) {
         println!("{}", self.root_pos);
         let moves = MOVE_GEN.generate_legal_moves(&self.root_pos);
@@ -207,17 +438,26 @@ impl Engine {
         );
         println!("Mobility bonus: {}", bonus_for_mobility(&self.root_pos));
 
+        match self.pawn_table.stats() {
+            Some((hits, probes)) => println!(
+                "Pawn table hits: {hits}/{probes} ({:.1}%)",
+                100.0 * hits as f64 / probes.max(1) as f64
+            ),
+            None => println!("Pawn table hits: counted with the Debug option"),
+        }
+
         println!();
         println!(
             "Eval: {}",
-            evaluate(&self.root_pos, &self.evaluation_table,)
+            evaluate(&self.root_pos, &self.evaluation_table, &self.pawn_table)
         );
     }
 
     fn uci_new_game(&mut self) {
+        self.root = None;
         self.root_pos = Position::default();
         self.repetition_table.clear();
-        self.transposition_table = Arc::new(TranspositionTable::new(self.options.hash));
+        self.transposition_table.clear();
         self.age = 0;
     }
 
@@ -245,6 +485,7 @@ impl Engine {
             println!("{}", self.root_pos);
             self.go(opts);
             thread::sleep(std::time::Duration::from_secs(2));
+            self.stop();
             dbg!();
             dbg!();
             dbg!(self.age);
@@ -258,10 +499,21 @@ fn uci_info() {
     println!("option name Move Overhead type spin default 10 min 0 max 5000");
     println!("option name Threads type spin default 10 min 1 max 1024");
     println!("option name Hash type spin default 16 min 1 max 33554432");
+    println!("option name EvalCache type spin default 16 min 1 max 65536");
+    println!("option name Clear Hash type button");
+    println!("option name HashFile type string default <empty>");
+    println!("option name Save Hash type button");
+    println!("option name Load Hash type button");
+    println!("option name Ponder type check default false");
+    println!("option name UCI_Chess960 type check default false");
+    println!("option name MultiPV type spin default 1 min 1 max {MAX_MOVES}");
+    println!("option name OwnBook type check default false");
+    println!("option name BookFile type string default <empty>");
+    println!("option name BookDepth type spin default 20 min 0 max 1000");
     println!("uciok");
 }
 
-fn parse_uci_moves(moves: Vec<String>, pos: &mut Position) -> Result<Table> {
+fn parse_uci_moves(moves: Vec<String>, pos: &mut Position, chess960: bool) -> Result<Table> {
     let mut repetition_table = Table::default();
     repetition_table.last_irreversible[0] = pos.halfmove_clock as usize;
 
@@ -269,13 +521,117 @@ fn parse_uci_moves(moves: Vec<String>, pos: &mut Position) -> Result<Table> {
         let mv = MOVE_GEN
             .generate_legal_moves(pos)
             .into_iter()
-            .find(|mv| mv.to_string() == mv_str)
+            .find(|mv| mv.to_uci(chess960) == mv_str)
+            // Not part of UCI, but handy when pasting moves from a PGN.
+            .or_else(|| MOVE_GEN.from_san(pos, &mv_str).ok())
             .ok_or(anyhow!("Invalid move: {mv_str}"))?;
 
-        let old_pos = pos.clone();
-        let _ = pos.make_move(&mv).map_err(anyhow::Error::from);
-        repetition_table.push(pos, mv.is_irreversible(&old_pos));
+        let irreversible = mv.is_irreversible(pos);
+        let _ = pos.make_move(&mv);
+        repetition_table.push(pos, irreversible);
     }
 
     Ok(repetition_table)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn infinite() -> Command {
+        Command::Go(SearchOptions {
+            infinite: true,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_search_lifecycle() {
+        let fen = "4k3/8/8/8/8/8/8/4K2R w K - 0 1";
+        let mut engine = Engine::default();
+
+        engine.handle_command(infinite());
+        assert!(engine.is_searching());
+
+        // Commands changing the state, the next search and `isready` wait for the search.
+        let pos = Position::from_fen(fen.to_string()).unwrap();
+        engine.handle_command(Command::Position(pos, vec![]));
+        engine.handle_command(Command::SetOption(
+            "Threads".to_string(),
+            Some("2".to_string()),
+        ));
+        engine.handle_command(infinite());
+        engine.handle_command(Command::IsReady);
+        assert!(engine.is_searching());
+        assert_eq!(engine.pending.len(), 4);
+        assert_eq!(engine.root_pos.to_fen(), Position::default().to_fen());
+        assert_eq!(engine.options.threads, 1);
+
+        // Stopping applies the queue, which starts the next search.
+        engine.handle_command(Command::Stop);
+        assert!(engine.is_searching());
+        assert!(engine.pending.is_empty());
+        assert_eq!(engine.root_pos.to_fen(), fen);
+        assert_eq!(engine.options.threads, 2);
+
+        engine.handle_command(Command::Stop);
+        assert!(!engine.is_searching());
+        assert!(engine.search.is_none());
+
+        // A single searchmove is not the only legal move, and `infinite` waits for `stop`.
+        engine.handle_command(Command::Go(SearchOptions {
+            infinite: true,
+            search_moves: vec!["h1h8".to_string()],
+            ..Default::default()
+        }));
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(engine.is_searching());
+        engine.handle_command(Command::Stop);
+        assert!(!engine.is_searching());
+
+        // `go perft` runs on the search thread too, so it can be stopped.
+        engine.handle_command(Command::Go(SearchOptions {
+            perft: Some(8),
+            ..Default::default()
+        }));
+        assert!(engine.is_searching());
+        engine.handle_command(Command::Stop);
+        assert!(!engine.is_searching());
+
+        // `quit` stops the search before the command thread ends.
+        let (tx, engine_thread) = Engine::start_loop_thread();
+        tx.send(infinite()).unwrap();
+        tx.send(Command::Quit).unwrap();
+        engine_thread.join().unwrap();
+    }
+
+    #[test]
+    fn test_position_continues_root() {
+        let mut engine = Engine::default();
+        let moves = |moves: &str| moves.split_whitespace().map(ToString::to_string).collect();
+
+        engine.handle_command(Command::Position(Position::default(), moves("e2e4")));
+        let age = engine.age;
+
+        // The game goes on, the age stays.
+        engine.handle_command(Command::Position(
+            Position::default(),
+            moves("e2e4 e7e5 g1f3"),
+        ));
+        assert_eq!(engine.age, age);
+
+        // Another game gets a new age.
+        engine.handle_command(Command::Position(Position::default(), moves("d2d4")));
+        assert_eq!(engine.age, age + 1);
+
+        // An invalid move keeps the previous root with its repetition table.
+        let repetition_idx = engine.repetition_table.idx;
+        engine.handle_command(Command::Position(Position::default(), moves("d2d4 e2e5")));
+        assert_eq!(engine.age, age + 1);
+        assert_eq!(engine.repetition_table.idx, repetition_idx);
+        assert_eq!(
+            engine.root,
+            Some((Position::default().to_fen(), moves("d2d4")))
+        );
+    }
+}
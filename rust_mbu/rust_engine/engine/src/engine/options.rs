@@ -1,7 +1,14 @@
-use crate::engine::Engine;
+use std::sync::Arc;
+
+use crate::engine::{
+    eval::evaluation_table::EvaluationTable,
+    search::heuristics::transposition_table::TranspositionTable, Engine,
+};
 use anyhow::anyhow;
 use derivative::Derivative;
+use move_gen::{generators::movegen::MAX_MOVES, utils::book::Book};
 
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Derivative, Copy, Clone)]
 #[derivative(Default)]
 pub struct Options {
@@ -11,7 +18,19 @@ pub struct Options {
     pub threads: usize,
     #[derivative(Default(value = "16"))]
     pub hash: usize,
+    /// Size of the evaluation cache in MB.
+    #[derivative(Default(value = "16"))]
+    pub eval_cache: usize,
     pub debug: bool,
+    pub chess960: bool,
+    /// Only tells that the GUI may send `go ponder`, pondering itself is driven by the GUI.
+    pub ponder: bool,
+    #[derivative(Default(value = "1"))]
+    pub multi_pv: usize,
+    pub own_book: bool,
+    /// Last full move number for which book moves are played.
+    #[derivative(Default(value = "20"))]
+    pub book_depth: usize,
 }
 
 impl Engine {
@@ -43,9 +62,86 @@ impl Engine {
         match name.replace(' ', "").to_lowercase().as_str() {
             "moveoverhead" => parse_set_field!(move_overhead, 0, 1000),
             "threads" => parse_set_field!(threads, 1, 1024),
-            "hash" => parse_set_field!(hash, 1, 33_554_432),
-            "debug" => self.options.debug = true,
+            "multipv" => parse_set_field!(multi_pv, 1, MAX_MOVES),
+            // Tables are only rebuilt for a valid new size, their entries are lost on the way.
+            "hash" => {
+                let old_size = self.options.hash;
+                parse_set_field!(hash, 1, 33_554_432);
+                if self.options.hash != old_size {
+                    self.transposition_table = Arc::new(TranspositionTable::new(self.options.hash));
+                }
+            }
+            "evalcache" => {
+                let old_size = self.options.eval_cache;
+                parse_set_field!(eval_cache, 1, 65_536);
+                if self.options.eval_cache != old_size {
+                    self.evaluation_table = Arc::new(EvaluationTable::new(self.options.eval_cache));
+                }
+            }
+            "hashfile" => match value.as_deref() {
+                None | Some("<empty>") => self.hash_file = None,
+                Some(path) => self.hash_file = Some(path.to_string()),
+            },
+            "savehash" => self.save_hash(),
+            "loadhash" => self.load_hash(),
+            "clearhash" => {
+                self.transposition_table.clear();
+                self.evaluation_table.clear();
+                self.pawn_table.clear();
+            }
+            "debug" => {
+                self.options.debug = true;
+                self.pawn_table.set_count_stats(true);
+            }
+            "uci_chess960" => match value.as_deref() {
+                Some("true") => self.options.chess960 = true,
+                Some("false") => self.options.chess960 = false,
+                _ => println!("Value for UCI_Chess960 must be true or false"),
+            },
+            "ponder" => match value.as_deref() {
+                Some("true") => self.options.ponder = true,
+                Some("false") => self.options.ponder = false,
+                _ => println!("Value for Ponder must be true or false"),
+            },
+            "ownbook" => match value.as_deref() {
+                Some("true") => self.options.own_book = true,
+                Some("false") => self.options.own_book = false,
+                _ => println!("Value for OwnBook must be true or false"),
+            },
+            "bookfile" => match value.as_deref() {
+                None | Some("<empty>") => self.book = None,
+                Some(path) => match Book::open(path) {
+                    Ok(book) => self.book = Some(book),
+                    Err(e) => println!("info string Couldn't open book {path}: {e}"),
+                },
+            },
+            "bookdepth" => parse_set_field!(book_depth, 0, 1000),
             _ => println!("Unknown option {name}"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_table_size() {
+        let mut engine = Engine::default();
+        let table = engine.transposition_table.clone();
+
+        // Invalid and unchanged sizes keep the table with its entries.
+        engine.set_option("Hash", Some("abc".to_string()));
+        engine.set_option("Hash", Some("0".to_string()));
+        engine.set_option("Hash", Some("16".to_string()));
+        assert!(Arc::ptr_eq(&table, &engine.transposition_table));
+
+        engine.set_option("Hash", Some("1".to_string()));
+        assert_eq!(engine.options.hash, 1);
+        assert!(!Arc::ptr_eq(&table, &engine.transposition_table));
+
+        let table = engine.evaluation_table.clone();
+        engine.set_option("EvalCache", Some("abc".to_string()));
+        assert!(Arc::ptr_eq(&table, &engine.evaluation_table));
+    }
+}
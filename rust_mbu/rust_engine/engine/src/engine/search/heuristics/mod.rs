@@ -2,6 +2,7 @@ pub mod futility_pruning;
 pub mod late_move_pruning;
 pub mod late_move_reduction;
 pub mod move_order;
+pub mod move_picker;
 pub mod null_move_reduction;
 pub mod razoring;
 pub mod static_exchange_evaluation;
@@ -15,87 +15,56 @@ static MVV_LVA: [[i32; 6]; 6] = [
     [100, 200, 300, 400, 500, 600],
 ];
 
-pub trait MoveUtils {
-    fn score_move(&self, mv: &Move, pos: &Position) -> i32;
-    fn order_moves(&self, moves: &mut [Move], pos: &Position, best_move: Option<Move>) {
-        let mut scores = [i32::MIN; 128];
-        for i in 0..moves.len() {
-            let mov = &moves[i];
-            let score = if best_move.as_ref().is_some_and(|best| best == mov) {
-                -3_000_000
-            } else {
-                -self.score_move(mov, pos)
-            };
+/// Most valuable victim - least valuable attacker score of a capture. A promotion without a
+/// capture is scored as the pawn capturing the promoted piece.
+#[must_use]
+pub fn mvv_lva(mv: &Move, pos: &Position) -> i32 {
+    let attacker = pos.piece_at(mv.from()).unwrap().0;
+    let victim = if !mv.is_capture() {
+        mv.promotion().unwrap()
+    } else if mv.is_enpass_capture() {
+        let rank_offset = if pos.turn == Color::White { -1 } else { 1 };
+        let sq = mv.to().offset(rank_offset, 0).unwrap();
+        pos.piece_at(sq).unwrap().0
+    } else {
+        pos.piece_at(mv.to()).unwrap().0
+    };
 
-            scores[i] = score;
-
-            // Sort the moves by score
-            let mut c = i;
-            let mut j = i;
-            loop {
-                if c > 0 {
-                    c -= 1;
-                } else {
-                    break;
-                }
-
-                if scores[j] < scores[c] {
-                    unsafe {
-                        std::ptr::swap(
-                            std::ptr::addr_of_mut!(moves[j]),
-                            std::ptr::addr_of_mut!(moves[c]),
-                        );
-                        std::ptr::swap(
-                            std::ptr::addr_of_mut!(scores[j]),
-                            std::ptr::addr_of_mut!(scores[c]),
-                        );
-                    };
-                    j -= 1;
-                } else {
-                    break;
-                }
-            }
-        }
-    }
+    MVV_LVA[attacker as usize][victim as usize]
 }
 
-impl MoveUtils for SearchData {
-    fn score_move(&self, mv: &Move, pos: &Position) -> i32 {
-        // We prioritze captures
-        if mv.is_capture() {
-            let attacker = pos.piece_at(mv.from()).unwrap().0;
-            let victim = if mv.is_enpass_capture() {
-                let rank_offset = if pos.turn == Color::White { -1 } else { 1 };
-                let sq = mv.to().offset(rank_offset, 0).unwrap();
-                pos.piece_at(sq).unwrap().0
-            } else {
-                pos.piece_at(mv.to()).unwrap().0
-            };
+impl SearchData {
+    /// Quiet moves that caused a beta cutoff in similar positions: killer moves, counter moves
+    /// and pair moves, in order of priority.
+    #[must_use]
+    pub fn refutations(&self) -> [Option<Move>; 6] {
+        let mut refutations = [
+            self.killer_moves[0][self.ply],
+            self.killer_moves[1][self.ply],
+            None,
+            None,
+            None,
+            None,
+        ];
 
-            return MVV_LVA[attacker as usize][victim as usize] + 1_000_000;
+        if self.ply > 1 {
+            refutations[2] = self.counter_moves[0][self.ply - 1];
+            refutations[3] = self.counter_moves[1][self.ply - 1];
         }
 
-        // Then we prioritize killer moves, that is moves that caused a beta cutoff in the past.
-        if self.killer_moves[0][self.ply].is_some_and(|killer| killer == *mv) {
-            500_000
-        } else if self.killer_moves[1][self.ply].is_some_and(|killer| killer == *mv) {
-            490_000
-        } else if self.ply > 1
-            && self.counter_moves[0][self.ply - 1].is_some_and(|counter| counter == *mv)
-        {
-            480_000
-        } else if self.ply > 1
-            && self.counter_moves[1][self.ply - 1].is_some_and(|counter| counter == *mv)
-        {
-            470_000
-        } else if self.ply > 2 && self.pair_moves[0][self.ply - 2].is_some_and(|pair| pair == *mv) {
-            460_000
-        } else if self.ply > 2 && self.pair_moves[1][self.ply - 2].is_some_and(|pair| pair == *mv) {
-            450_000
-        } else {
-            let (piece, color) = pos.piece_at(mv.from()).unwrap();
-
-            self.history_moves[color as usize][piece as usize][mv.to() as usize]
+        if self.ply > 2 {
+            refutations[4] = self.pair_moves[0][self.ply - 2];
+            refutations[5] = self.pair_moves[1][self.ply - 2];
         }
+
+        refutations
     }
 }
+
+/// History score of a quiet move.
+#[must_use]
+pub fn history_score(history: &[Vec<Vec<i32>>], mv: &Move, pos: &Position) -> i32 {
+    let (piece, color) = pos.piece_at(mv.from()).unwrap();
+
+    history[color as usize][piece as usize][mv.to() as usize]
+}
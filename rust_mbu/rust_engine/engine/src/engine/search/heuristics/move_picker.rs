@@ -0,0 +1,381 @@
+use move_gen::{
+    generators::movegen::{MoveFilter, MAX_MOVES},
+    r#move::{move_list::MoveList, Move},
+};
+use sdk::position::{Piece, Position};
+
+use crate::engine::{eval::PIECE_VALUES, MOVE_GEN};
+
+use super::{
+    move_order::{history_score, mvv_lva},
+    static_exchange_evaluation::static_exchange_evaluation,
+};
+
+/// Score offset of captures losing material, which puts them below every other capture.
+const LOSING_CAPTURE: i32 = -10_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stage {
+    TTMove,
+    ScoreCaptures,
+    GoodCaptures,
+    Refutations,
+    ScoreQuiets,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+/// Hands out moves of a node one by one, in the order they should be searched:
+/// 1. the transposition table move,
+/// 2. captures and queen promotions that don't lose material, by MVV-LVA,
+/// 3. killer, counter and pair moves,
+/// 4. remaining quiet moves, by history,
+/// 5. captures losing material according to static exchange evaluation.
+///
+/// The transposition table move and refutations are only checked for legality, captures and quiet
+/// moves are generated separately and every stage is scored and sorted only when it is reached,
+/// so a cutoff on an early move saves the work for the rest of the list.
+pub struct MovePicker {
+    stage: Stage,
+    tt_move: Option<Move>,
+    /// In quiescence search only captures that don't lose material are searched.
+    captures_only: bool,
+    /// Moves were given up front, so the transposition table move and refutations have to be
+    /// among them.
+    given_moves: bool,
+    /// Captures are kept in front of the quiet moves.
+    moves: MoveList,
+    scores: [i32; MAX_MOVES],
+    /// Index of the first capture not returned yet.
+    next_capture: usize,
+    /// Index of the first quiet move, which is also the end of the captures.
+    quiets_start: usize,
+    /// Index of the first quiet move not returned yet.
+    next_quiet: usize,
+    refutations: [Option<Move>; 6],
+    next_refutation: usize,
+}
+
+impl MovePicker {
+    /// Picker for all legal moves of a node, generated once they are needed. Refutations are
+    /// the killer, counter and pair moves of the node.
+    #[must_use]
+    pub fn new(tt_move: Option<Move>, refutations: [Option<Move>; 6]) -> Self {
+        Self {
+            stage: Stage::TTMove,
+            tt_move,
+            captures_only: false,
+            given_moves: false,
+            moves: MoveList::new(),
+            scores: [0; MAX_MOVES],
+            next_capture: 0,
+            quiets_start: 0,
+            next_quiet: 0,
+            refutations,
+            next_refutation: 0,
+        }
+    }
+
+    /// Picker for an already generated list of moves, e.g. the root moves left after excluding
+    /// the ones given by `searchmoves` or other `MultiPV` lines.
+    #[must_use]
+    pub fn with_moves(
+        moves: &[Move],
+        tt_move: Option<Move>,
+        refutations: [Option<Move>; 6],
+    ) -> Self {
+        let mut picker = Self::new(tt_move, refutations);
+        picker
+            .moves
+            .extend(moves.iter().copied().filter(|mv| is_tactical(*mv)));
+        picker.quiets_start = picker.moves.len();
+        picker.next_quiet = picker.moves.len();
+        picker
+            .moves
+            .extend(moves.iter().copied().filter(|mv| !is_tactical(*mv)));
+        picker.given_moves = true;
+
+        picker
+    }
+
+    /// Picker for quiescence search, returning only captures and queen promotions that don't
+    /// lose material.
+    #[must_use]
+    pub fn captures() -> Self {
+        let mut picker = Self::new(None, [None; 6]);
+        picker.captures_only = true;
+
+        picker
+    }
+
+    /// Returns the next move to search, or `None` if there are no more moves. Quiet moves are
+    /// ordered by the `history` table.
+    pub fn next(&mut self, pos: &Position, history: &[Vec<Vec<i32>>]) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::TTMove => {
+                    self.stage = Stage::ScoreCaptures;
+                    self.tt_move = self.tt_move.filter(|mv| self.is_valid(pos, *mv));
+                    if self.tt_move.is_some() {
+                        return self.tt_move;
+                    }
+                }
+                Stage::ScoreCaptures => {
+                    self.generate_captures(pos);
+                    self.remove_tt_capture();
+                    self.score_captures(pos);
+                    self.stage = Stage::GoodCaptures;
+                }
+                Stage::GoodCaptures => {
+                    if let Some(mv) = self.pick_best(self.next_capture, self.quiets_start, 0) {
+                        self.next_capture += 1;
+                        return Some(mv);
+                    }
+
+                    self.stage = if self.captures_only {
+                        Stage::Done
+                    } else {
+                        Stage::Refutations
+                    };
+                }
+                Stage::Refutations => {
+                    if let Some(mv) = self.next_refutation(pos) {
+                        return Some(mv);
+                    }
+
+                    self.stage = Stage::ScoreQuiets;
+                }
+                Stage::ScoreQuiets => {
+                    self.generate_quiets(pos);
+                    self.remove_returned_quiets();
+                    for idx in self.next_quiet..self.moves.len() {
+                        self.scores[idx] = history_score(history, &self.moves[idx], pos);
+                    }
+
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => {
+                    if let Some(mv) = self.pick_best(self.next_quiet, self.moves.len(), i32::MIN) {
+                        self.next_quiet += 1;
+                        return Some(mv);
+                    }
+
+                    self.stage = Stage::BadCaptures;
+                }
+                Stage::BadCaptures => {
+                    if let Some(mv) = self.pick_best(self.next_capture, self.quiets_start, i32::MIN)
+                    {
+                        self.next_capture += 1;
+                        return Some(mv);
+                    }
+
+                    self.stage = Stage::Done;
+                }
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    /// Moves from the transposition table and killer tables may come from another position.
+    fn is_valid(&self, pos: &Position, mv: Move) -> bool {
+        if self.given_moves {
+            self.moves.contains(&mv)
+        } else {
+            MOVE_GEN.is_legal(pos, &mv)
+        }
+    }
+
+    fn remove_tt_capture(&mut self) {
+        let Some(tt_move) = self.tt_move else {
+            return;
+        };
+
+        if let Some(idx) = self.moves[..self.quiets_start]
+            .iter()
+            .position(|mv| *mv == tt_move)
+        {
+            self.moves.remove(idx);
+            self.quiets_start -= 1;
+            self.next_quiet -= 1;
+        }
+    }
+
+    /// Removes the transposition table move and refutations, which were already returned.
+    fn remove_returned_quiets(&mut self) {
+        let mut idx = self.next_quiet;
+        while idx < self.moves.len() {
+            let mv = Some(self.moves[idx]);
+            if mv == self.tt_move || self.refutations.contains(&mv) {
+                self.moves.remove(idx);
+            } else {
+                idx += 1;
+            }
+        }
+    }
+
+    fn generate_captures(&mut self, pos: &Position) {
+        if !self.given_moves {
+            MOVE_GEN.generate_into(pos, MoveFilter::Captures, &mut self.moves);
+            self.quiets_start = self.moves.len();
+            self.next_quiet = self.moves.len();
+        }
+    }
+
+    fn generate_quiets(&mut self, pos: &Position) {
+        if !self.given_moves {
+            MOVE_GEN.generate_into(pos, MoveFilter::Quiets, &mut self.moves);
+        }
+    }
+
+    fn score_captures(&mut self, pos: &Position) {
+        for idx in 0..self.quiets_start {
+            let mv = self.moves[idx];
+            self.scores[idx] = if is_losing_capture(mv, pos) {
+                mvv_lva(&mv, pos) + LOSING_CAPTURE
+            } else {
+                mvv_lva(&mv, pos)
+            };
+        }
+    }
+
+    /// Returns the next killer, counter or pair move which is a legal quiet move not returned
+    /// yet. Refutations which aren't returned are cleared, so only the returned ones are left.
+    fn next_refutation(&mut self, pos: &Position) -> Option<Move> {
+        while self.next_refutation < self.refutations.len() {
+            let idx = self.next_refutation;
+            self.next_refutation += 1;
+
+            let Some(refutation) = self.refutations[idx] else {
+                continue;
+            };
+
+            if !is_tactical(refutation)
+                && Some(refutation) != self.tt_move
+                && !self.refutations[..idx].contains(&Some(refutation))
+                && self.is_valid(pos, refutation)
+            {
+                return Some(refutation);
+            }
+
+            self.refutations[idx] = None;
+        }
+
+        None
+    }
+
+    /// Moves the best scored move of `moves[start..end]` to `start` and returns it, unless its
+    /// score is below `min_score`. Moves with equal scores keep their order.
+    fn pick_best(&mut self, start: usize, end: usize, min_score: i32) -> Option<Move> {
+        let mut best = start;
+        for idx in start + 1..end {
+            if self.scores[idx] > self.scores[best] {
+                best = idx;
+            }
+        }
+
+        if start >= end || self.scores[best] < min_score {
+            return None;
+        }
+
+        self.moves[start..=best].rotate_right(1);
+        self.scores[start..=best].rotate_right(1);
+        Some(self.moves[start])
+    }
+}
+
+/// Captures and queen promotions, which are generated and searched before quiet moves.
+fn is_tactical(mv: Move) -> bool {
+    mv.is_capture() || mv.promotion() == Some(Piece::Queen)
+}
+
+/// Whether the capture loses material. Only captures of a cheaper piece need the static exchange
+/// evaluation.
+fn is_losing_capture(mv: Move, pos: &Position) -> bool {
+    if !mv.is_capture() || mv.is_enpass_capture() {
+        return false;
+    }
+
+    let attacker = pos.piece_at(mv.from()).unwrap().0;
+    let victim = pos.piece_at(mv.to()).unwrap().0;
+
+    PIECE_VALUES[attacker as usize] > PIECE_VALUES[victim as usize]
+        && static_exchange_evaluation(pos, &mv) < 0
+}
+
+#[cfg(test)]
+mod tests {
+    use sdk::fen::Fen;
+
+    use super::*;
+
+    fn picked_moves(picker: &mut MovePicker, pos: &Position) -> Vec<String> {
+        let history = vec![vec![vec![0; 64]; 6]; 2];
+
+        std::iter::from_fn(|| picker.next(pos, &history))
+            .map(|mv| mv.to_string())
+            .collect()
+    }
+
+    fn find_move(pos: &Position, uci: &str) -> Move {
+        MOVE_GEN
+            .generate_legal_moves(pos)
+            .into_iter()
+            .find(|mv| mv.to_string() == uci)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_move_picker_stages() {
+        // The rook capture on d5 loses the rook to the pawn on e6.
+        let pos = Position::from_fen("4k3/8/4p3/3p4/8/3R4/8/3QK3 w - - 0 1".to_string()).unwrap();
+        let tt_move = find_move(&pos, "e1f2");
+        let killer = find_move(&pos, "d1a4");
+
+        let mut picker = MovePicker::new(
+            Some(tt_move),
+            [Some(killer), Some(tt_move), None, None, None, None],
+        );
+        let moves = picked_moves(&mut picker, &pos);
+
+        assert_eq!(moves.len(), MOVE_GEN.generate_legal_moves(&pos).len());
+        assert_eq!(moves[0], "e1f2");
+        assert_eq!(moves[1], "d1a4");
+        assert_eq!(moves.last().unwrap(), "d3d5");
+
+        let mut picker = MovePicker::captures();
+        assert!(picked_moves(&mut picker, &pos).is_empty());
+    }
+
+    #[test]
+    fn test_move_picker_illegal_moves() {
+        // Moves from another position, as after a hash collision.
+        let other = Position::from_fen("4k3/8/8/8/8/8/3R4/4K3 w - - 0 1".to_string()).unwrap();
+        let tt_move = find_move(&other, "d2d8");
+        let killer = find_move(&other, "d2d1");
+
+        let pos = Position::from_fen("4k3/8/8/8/8/8/8/3RK3 w - - 0 1".to_string()).unwrap();
+        let mut picker =
+            MovePicker::new(Some(tt_move), [Some(killer), None, None, None, None, None]);
+        let mut moves = picked_moves(&mut picker, &pos);
+        moves.sort();
+
+        let mut legal: Vec<String> = MOVE_GEN
+            .generate_legal_moves(&pos)
+            .into_iter()
+            .map(|mv| mv.to_string())
+            .collect();
+        legal.sort();
+
+        assert_eq!(moves, legal);
+    }
+
+    #[test]
+    fn test_move_picker_captures() {
+        let pos = Position::from_fen("4k3/2P5/8/1r1n4/2P5/8/8/4K3 w - - 0 1".to_string()).unwrap();
+
+        // Only the promotion to a queen is searched in quiescence.
+        let mut picker = MovePicker::captures();
+        assert_eq!(picked_moves(&mut picker, &pos), ["c7c8q", "c4b5", "c4d5"]);
+    }
+}
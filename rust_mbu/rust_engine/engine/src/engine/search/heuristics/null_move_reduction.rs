@@ -11,7 +11,7 @@ impl SearchData {
     /// [Source](https://web.archive.org/web/20071031095933/http://www.brucemo.com/compchess/programming/nullmove.htm)
     pub fn null_move_reduction(
         &mut self,
-        node: &Position,
+        node: &mut Position,
         beta: i32,
         depth: usize,
         in_check: bool,
@@ -21,22 +21,19 @@ impl SearchData {
             return false;
         }
 
-        let child = {
-            let mut child = node.clone();
-            child.make_null_move();
-            child
-        };
+        let undo = node.make_null_move();
 
-        self.repetition_table.push(&child, false);
+        self.repetition_table.push(node, false);
         self.ply += 1;
         let score = -self.negamax(
-            &child,
+            node,
             -beta,
             -beta + 1,
             depth - NULL_MOVE_DEPTH_REDUCTION - 1,
         );
         self.ply -= 1;
         self.repetition_table.decrement();
+        node.unmake_null_move(&undo);
 
         score >= beta
     }
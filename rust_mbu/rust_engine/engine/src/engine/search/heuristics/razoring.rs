@@ -8,7 +8,7 @@ impl SearchData {
     /// Returns `new_score`
     pub fn razoring(
         &mut self,
-        node: &Position,
+        node: &mut Position,
         static_eval: i32,
         alpha: i32,
         beta: i32,
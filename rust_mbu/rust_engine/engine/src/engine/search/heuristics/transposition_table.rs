@@ -1,6 +1,11 @@
 use move_gen::r#move::Move;
+use sdk::hash::ZOBRIST_KEYS;
 use sdk::position::Position;
-use std::sync::atomic::{AtomicU64, Ordering};
+use sdk::zeroed::{Zeroable, ZeroedTable};
+use std::{
+    io::{self, Read, Write},
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 use crate::engine::search::MATE_SCORE;
 
@@ -11,11 +16,19 @@ pub enum HashFlag {
     BETA,
 }
 
+/// Key XOR-ed with the data, and the data. The key word also holds the static evaluation in its
+/// low 16 bits, so only the upper 48 bits of the key are verified.
 pub type TTEntry = [AtomicU64; 2];
 
+/// Entries sharing one cache line.
+pub const CLUSTER_SIZE: usize = 4;
+
+#[repr(align(64))]
+pub struct Cluster([TTEntry; CLUSTER_SIZE]);
+
 pub struct TranspositionTable {
     size: usize,
-    inner: Vec<TTEntry>,
+    inner: ZeroedTable<Cluster>,
 }
 
 impl Default for TranspositionTable {
@@ -24,6 +37,9 @@ impl Default for TranspositionTable {
     }
 }
 
+// SAFETY: A cluster is only made of atomics.
+unsafe impl Zeroable for Cluster {}
+
 // 32 Bits for score
 pub const SCORE_SHIFT: u64 = 64 - 32;
 pub const SCORE_MASK: u64 = 0b1111_1111_1111_1111_1111_1111_1111_1111 << SCORE_SHIFT;
@@ -40,6 +56,8 @@ pub const MOVE_MASK: u64 = 0b1111_1111_1111_1111 << MOVE_SHIFT;
 // 7 Bits for age
 pub const AGE_SHIFT: u64 = 64 - 32 - 7 - 16 - 7;
 pub const AGE_MASK: u64 = 0b111_1111 << AGE_SHIFT;
+/// Ages are stored modulo this.
+pub const AGE_MODULO: usize = 1 << 7;
 
 // 2 Bits for flag
 pub const FLAG_MASK: u64 = 0b11;
@@ -51,27 +69,184 @@ fn pack_tt_entry(score: i32, mv: Option<Move>, depth: usize, age: usize, flag: H
     packed |= (score as u64) << SCORE_SHIFT;
     packed |= (depth as u64) << DEPTH_SHIFT;
     packed |= u64::from(mv.unwrap_or(Move::null()).inner) << MOVE_SHIFT;
-    packed |= (age as u64) << AGE_SHIFT;
+    packed |= ((age % AGE_MODULO) as u64) << AGE_SHIFT;
     packed |= flag as u64;
 
     packed
 }
 
-#[allow(clippy::declare_interior_mutable_const)]
-const DEFAULT_ENTRY: TTEntry = [AtomicU64::new(0), AtomicU64::new(0)];
+/// Start of files saved with [`TranspositionTable::save`].
+pub const FILE_MAGIC: [u8; 4] = *b"MBTT";
+/// Changes whenever the layout of entries does.
+pub const FILE_VERSION: u32 = 1;
+/// Magic, version, number of clusters, fingerprint of the Zobrist keys and age.
+pub const FILE_HEADER_SIZE: usize = 4 + 4 + 8 + 8 + 8;
+
+/// Verified bits of the key word.
+pub const KEY_MASK: u64 = !0xFFFF;
+/// Static evaluation of entries written without one.
+pub const NO_EVAL: i16 = i16::MIN;
+
+fn pack_key(hash: u64, data: u64, static_eval: Option<i32>) -> u64 {
+    let eval = static_eval.map_or(NO_EVAL, |eval| {
+        eval.clamp(-i32::from(i16::MAX), i32::from(i16::MAX)) as i16
+    });
+
+    ((hash ^ data) & KEY_MASK) | u64::from(eval as u16)
+}
+
+const fn is_empty(key: u64, data: u64) -> bool {
+    key == 0 && data == 0
+}
+
+const fn matches(key: u64, data: u64, hash: u64) -> bool {
+    (key ^ data) & KEY_MASK == hash & KEY_MASK && !is_empty(key, data)
+}
+
+/// How valuable an entry is to keep. Deep entries and exact scores are preferred, and entries of
+/// earlier searches lose value with every search.
+fn entry_value(data: u64, age: usize) -> i32 {
+    let exact_bonus = if get_flag(data) == HashFlag::EXACT {
+        2
+    } else {
+        0
+    };
+
+    get_depth(data) as i32 + exact_bonus - 8 * age_distance(get_age(data), age) as i32
+}
 
 #[allow(clippy::too_many_arguments)]
 impl TranspositionTable {
+    #[must_use]
     pub fn new(size_in_mb: usize) -> Self {
-        let count = 1024 * 1024 * size_in_mb / std::mem::size_of::<TTEntry>();
+        let count = 1024 * 1024 * size_in_mb / std::mem::size_of::<Cluster>();
+
+        Self {
+            inner: ZeroedTable::new(count),
+            size: count,
+        }
+    }
+
+    pub fn clear(&self) {
+        for [key, data] in self.inner.iter().flat_map(|cluster| &cluster.0) {
+            key.store(0, Ordering::Relaxed);
+            data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn cluster(&self, hash: u64) -> &Cluster {
+        &self.inner[hash as usize % self.size]
+    }
 
-        let mut inner = Vec::with_capacity(count);
-        for _ in 0..count {
-            inner.push(DEFAULT_ENTRY);
+    /// Loads the key and data words of the entry of the position, if the cluster has one.
+    fn probe(&self, hash: u64) -> Option<(u64, u64)> {
+        self.cluster(hash).0.iter().find_map(|[key, data]| {
+            let (key, data) = (key.load(Ordering::Relaxed), data.load(Ordering::Relaxed));
+
+            matches(key, data, hash).then_some((key, data))
+        })
+    }
+
+    /// Writes the table with a header, so that it can be loaded in a later session. All words are
+    /// little-endian.
+    pub fn save(&self, mut writer: impl Write, age: usize) -> io::Result<()> {
+        writer.write_all(&FILE_MAGIC)?;
+        writer.write_all(&FILE_VERSION.to_le_bytes())?;
+        writer.write_all(&(self.size as u64).to_le_bytes())?;
+        writer.write_all(&ZOBRIST_KEYS.fingerprint().to_le_bytes())?;
+        writer.write_all(&(age as u64).to_le_bytes())?;
+
+        for [key, data] in self.inner.iter().flat_map(|cluster| &cluster.0) {
+            writer.write_all(&key.load(Ordering::Relaxed).to_le_bytes())?;
+            writer.write_all(&data.load(Ordering::Relaxed).to_le_bytes())?;
+        }
+
+        writer.flush()
+    }
+
+    /// Loads a table written by [`TranspositionTable::save`] and returns the age it was saved
+    /// with. Files of another version, Zobrist keys or table size are refused, and the table is
+    /// left empty if the file turns out to be truncated.
+    pub fn load(&self, mut reader: impl Read) -> io::Result<usize> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let mut header = [0; FILE_HEADER_SIZE];
+        reader.read_exact(&mut header)?;
+        let word =
+            |offset: usize| u64::from_le_bytes(header[offset..offset + 8].try_into().unwrap());
+
+        if header[..4] != FILE_MAGIC {
+            return Err(invalid("Not a transposition table file".to_string()));
+        }
+        let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        if version != FILE_VERSION {
+            return Err(invalid(format!(
+                "Unsupported file version {version}, expected {FILE_VERSION}"
+            )));
+        }
+        let clusters = word(8) as usize;
+        if clusters != self.size {
+            let size_in_mb = clusters * std::mem::size_of::<Cluster>() / (1024 * 1024);
+            return Err(invalid(format!(
+                "File was saved with Hash {size_in_mb}, set Hash to the same size to load it"
+            )));
         }
+        if word(16) != ZOBRIST_KEYS.fingerprint() {
+            return Err(invalid(
+                "File was saved with different Zobrist keys".to_string(),
+            ));
+        }
+
+        let mut words = [0; 16];
+        for [key, data] in self.inner.iter().flat_map(|cluster| &cluster.0) {
+            if let Err(e) = reader.read_exact(&mut words) {
+                self.clear();
+                return Err(e);
+            }
+            key.store(
+                u64::from_le_bytes(words[..8].try_into().unwrap()),
+                Ordering::Relaxed,
+            );
+            data.store(
+                u64::from_le_bytes(words[8..].try_into().unwrap()),
+                Ordering::Relaxed,
+            );
+        }
+        if reader.read(&mut [0])? != 0 {
+            self.clear();
+            return Err(invalid("File is larger than the table".to_string()));
+        }
+
+        Ok(word(24) as usize)
+    }
 
-        Self { inner, size: count }
+    /// Permille of the table used by entries of the given age, sampled from the first 1000
+    /// entries as in the UCI `hashfull` info.
+    #[must_use]
+    pub fn hashfull(&self, age: usize) -> usize {
+        let sample = self.size.min(1000 / CLUSTER_SIZE);
+        let used = self.inner[..sample]
+            .iter()
+            .flat_map(|cluster| &cluster.0)
+            .filter(|[key, data]| {
+                let (key, data) = (key.load(Ordering::Relaxed), data.load(Ordering::Relaxed));
+
+                !is_empty(key, data) && get_age(data) == age % AGE_MODULO
+            })
+            .count();
+
+        used * 1000 / (sample * CLUSTER_SIZE).max(1)
     }
+
+    /// Static evaluation stored with the position, which saves evaluating it again.
+    #[must_use]
+    pub fn static_eval(&self, hash: u64) -> Option<i32> {
+        let (key, _) = self.probe(hash)?;
+        let eval = key as u16 as i16;
+
+        (eval != NO_EVAL).then_some(i32::from(eval))
+    }
+
     #[must_use]
     pub fn cashed_value(
         &self,
@@ -98,48 +273,42 @@ impl TranspositionTable {
         depth: usize,
         ply: usize,
     ) -> (Option<i32>, Option<Move>) {
-        let [hash_lock, entry_lock] = &self.inner[hash as usize % self.size];
-        let tt_hash = hash_lock.load(Ordering::Relaxed);
-        let tt_entry = entry_lock.load(Ordering::Relaxed);
-        if tt_hash != 0 {
-            if tt_hash != hash {
-                return (None, None);
-            }
-
-            if get_depth(tt_entry) < depth {
-                return (None, get_move(tt_entry));
-            }
+        let Some((_, tt_entry)) = self.probe(hash) else {
+            return (None, None);
+        };
 
-            let mut score = get_score(tt_entry);
+        if get_depth(tt_entry) < depth {
+            return (None, get_move(tt_entry));
+        }
 
-            if score < -MATE_SCORE {
-                score += ply as i32;
-            } else if score > MATE_SCORE {
-                score -= ply as i32;
-            }
+        let mut score = get_score(tt_entry);
 
-            (
-                match get_flag(tt_entry) {
-                    HashFlag::EXACT => Some(score),
-                    HashFlag::BETA => (score >= beta).then_some(beta),
-                    HashFlag::ALPHA => (score <= alpha).then_some(alpha),
-                },
-                get_move(tt_entry),
-            )
-        } else {
-            (None, None)
+        if score < -MATE_SCORE {
+            score += ply as i32;
+        } else if score > MATE_SCORE {
+            score -= ply as i32;
         }
+
+        (
+            match get_flag(tt_entry) {
+                HashFlag::EXACT => Some(score),
+                HashFlag::BETA => (score >= beta).then_some(beta),
+                HashFlag::ALPHA => (score <= alpha).then_some(alpha),
+            },
+            get_move(tt_entry),
+        )
     }
 
     pub fn write(
         &self,
         hash: u64,
         mut score: i32,
-        mv: Option<Move>,
+        mut mv: Option<Move>,
         depth: usize,
         ply: usize,
         flag: HashFlag,
         age: usize,
+        static_eval: Option<i32>,
     ) {
         if score < -MATE_SCORE {
             score -= ply as i32;
@@ -147,31 +316,50 @@ impl TranspositionTable {
             score += ply as i32;
         }
 
-        let new_entry = pack_tt_entry(score, mv, depth, age, flag);
-
-        let index = hash as usize % self.size;
-
-        let [old_hash_lock, old_entry_lock] = &self.inner[index];
-        let old_hash = old_hash_lock.load(Ordering::Relaxed);
-        let old_entry = old_entry_lock.load(Ordering::Relaxed);
-
-        if old_hash != 0 {
-            let old_age = get_age(old_entry);
-            let old_depth = get_depth(old_entry);
-
-            let replace = (old_age < age) || (old_age == age && old_depth < depth);
+        let cluster = &self.cluster(hash).0;
+
+        // The entry of the same position is updated, otherwise the least valuable one is replaced.
+        let mut replaced = 0;
+        let mut replaced_value = i32::MAX;
+        for (idx, [key_lock, entry_lock]) in cluster.iter().enumerate() {
+            let key = key_lock.load(Ordering::Relaxed);
+            let old_entry = entry_lock.load(Ordering::Relaxed);
+
+            if matches(key, old_entry, hash) {
+                let older = age_distance(get_age(old_entry), age) > 0;
+                if !older && get_depth(old_entry) > depth {
+                    return;
+                }
+                mv = mv.or(get_move(old_entry));
+                replaced = idx;
+                break;
+            }
 
-            if replace {
-                old_entry_lock.store(new_entry, Ordering::Relaxed);
-                old_hash_lock.store(hash, Ordering::Relaxed);
+            let value = if is_empty(key, old_entry) {
+                i32::MIN
+            } else {
+                entry_value(old_entry, age)
+            };
+            if value < replaced_value {
+                replaced = idx;
+                replaced_value = value;
             }
-        } else {
-            old_entry_lock.store(new_entry, Ordering::Relaxed);
-            old_hash_lock.store(hash, Ordering::Relaxed);
         }
+
+        let new_entry = pack_tt_entry(score, mv, depth, age, flag);
+        let [key_lock, entry_lock] = &cluster[replaced];
+        entry_lock.store(new_entry, Ordering::Relaxed);
+        key_lock.store(pack_key(hash, new_entry, static_eval), Ordering::Relaxed);
     }
 }
 
+/// How many searches ago an entry of `old_age` was written, where `age` may have wrapped around
+/// the 7 bits of the field since.
+#[must_use]
+pub fn age_distance(old_age: usize, age: usize) -> usize {
+    age.wrapping_sub(old_age) % AGE_MODULO
+}
+
 #[must_use]
 pub fn get_depth(packed: u64) -> usize {
     ((packed & DEPTH_MASK) >> DEPTH_SHIFT) as usize
@@ -215,19 +403,119 @@ mod tests {
     use move_gen::r#move::{Move, MoveKind};
     use sdk::square::Square;
 
+    use std::sync::atomic::Ordering;
+
     use super::{pack_tt_entry, TranspositionTable};
 
     #[test]
     fn test_tt() {
         let tt = TranspositionTable::new(1);
-        tt.write(11, 5, None, 4, 0, super::HashFlag::EXACT, 0);
-        tt.write(11, 6, None, 3, 0, super::HashFlag::EXACT, 1);
-        tt.write(11, 7, None, 5, 0, super::HashFlag::EXACT, 1);
-        tt.write(9, 70, None, 5, 0, super::HashFlag::EXACT, 2);
+        tt.write(11, 5, None, 4, 0, super::HashFlag::EXACT, 0, None);
+        tt.write(11, 6, None, 3, 0, super::HashFlag::EXACT, 1, None);
+        tt.write(11, 7, None, 5, 0, super::HashFlag::EXACT, 1, None);
+        tt.write(9, 70, None, 5, 0, super::HashFlag::EXACT, 2, None);
 
         assert_eq!(tt.read(11, 0, 0, 0, 0), (Some(7), None));
     }
 
+    #[test]
+    fn test_tt_age_wraparound() {
+        let tt = TranspositionTable::new(1);
+        tt.write(11, 5, None, 9, 0, super::HashFlag::EXACT, 127, None);
+        // Age 128 is stored as 0, but is still newer than 127.
+        tt.write(11, 6, None, 2, 0, super::HashFlag::EXACT, 128, None);
+        assert_eq!(tt.read(11, 0, 0, 0, 0), (Some(6), None));
+
+        // Within one search a shallower entry doesn't replace a deeper one.
+        tt.write(11, 7, None, 1, 0, super::HashFlag::EXACT, 128, None);
+        assert_eq!(tt.read(11, 0, 0, 0, 0), (Some(6), None));
+
+        assert_eq!(super::age_distance(126, 129), 3);
+        assert_eq!(
+            super::get_age(pack_tt_entry(0, None, 0, 300, super::HashFlag::BETA)),
+            44
+        );
+    }
+
+    #[test]
+    fn test_tt_cluster() {
+        assert_eq!(std::mem::size_of::<super::Cluster>(), 64);
+        let tt = TranspositionTable::new(1);
+        // All of these share the cluster, but differ in the verified bits of the key.
+        let hash = |idx: u64| (idx << 48) | 5;
+
+        for (idx, depth) in [(1, 10), (2, 1), (3, 2), (4, 3)] {
+            tt.write(
+                hash(idx),
+                0,
+                None,
+                depth,
+                0,
+                super::HashFlag::BETA,
+                1,
+                Some(-20),
+            );
+        }
+        tt.write(hash(5), 0, None, 1, 0, super::HashFlag::BETA, 1, None);
+        assert_eq!(tt.read(hash(2), 0, 0, 0, 0), (None, None));
+        assert_eq!(tt.read(hash(5), 0, 0, 0, 0), (Some(0), None));
+        assert_eq!(tt.static_eval(hash(1)), Some(-20));
+        assert_eq!(tt.static_eval(hash(5)), None);
+
+        // Entries of older searches go first, unless they are much deeper.
+        tt.write(hash(6), 0, None, 1, 0, super::HashFlag::BETA, 2, None);
+        assert_eq!(tt.read(hash(5), 0, 0, 0, 0), (None, None));
+        assert_eq!(tt.read(hash(1), 0, 0, 0, 0), (Some(0), None));
+
+        // Data of another entry doesn't verify with the key.
+        let [_, data] = &tt.inner[5].0[0];
+        let [_, other_data] = &tt.inner[5].0[1];
+        data.store(other_data.load(Ordering::Relaxed), Ordering::Relaxed);
+        assert_eq!(tt.read(hash(1), 0, 0, 0, 0), (None, None));
+    }
+
+    #[test]
+    fn test_tt_save_load() {
+        let tt = TranspositionTable::new(1);
+        tt.write(11, 5, None, 4, 0, super::HashFlag::EXACT, 9, Some(30));
+        let mut file = vec![];
+        tt.save(&mut file, 9).unwrap();
+        assert_eq!(file.len(), super::FILE_HEADER_SIZE + 1024 * 1024);
+
+        let loaded = TranspositionTable::new(1);
+        assert_eq!(loaded.load(file.as_slice()).unwrap(), 9);
+        assert_eq!(loaded.read(11, 0, 0, 0, 0), (Some(5), None));
+        assert_eq!(loaded.static_eval(11), Some(30));
+
+        // Other sizes, versions, keys and broken files are refused.
+        assert!(TranspositionTable::new(2).load(file.as_slice()).is_err());
+        for offset in [0, 4, 16] {
+            let mut other = file.clone();
+            other[offset] ^= 1;
+            assert!(loaded.load(other.as_slice()).is_err());
+        }
+        assert!(loaded.load(&file[..file.len() - 1]).is_err());
+        assert_eq!(loaded.read(11, 0, 0, 0, 0), (None, None));
+        file.push(0);
+        assert!(loaded.load(file.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_hashfull() {
+        let tt = TranspositionTable::new(1);
+        assert_eq!(tt.hashfull(0), 0);
+
+        for hash in 1..=100 {
+            tt.write(hash, 0, None, 1, 0, super::HashFlag::EXACT, 3, None);
+        }
+        assert_eq!(tt.hashfull(3), 100);
+        assert_eq!(tt.hashfull(4), 0);
+
+        tt.clear();
+        assert_eq!(tt.hashfull(3), 0);
+        assert_eq!(tt.read(1, 0, 0, 0, 0), (None, None));
+    }
+
     #[test]
     fn test_pack() {
         let depth = 9;
@@ -26,24 +26,19 @@ pub const DRAW_SCORE: i32 = 0;
 use self::{
     draw::can_win,
     heuristics::{
-        futility_pruning::is_futile,
-        late_move_reduction::is_lmr_applicable,
-        move_order::MoveUtils,
-        static_exchange_evaluation::{see_move_done, static_exchange_evaluation},
+        futility_pruning::is_futile, late_move_reduction::is_lmr_applicable,
+        move_picker::MovePicker, static_exchange_evaluation::see_move_done,
         transposition_table::HashFlag,
     },
     parallel::SearchData,
 };
-use lazy_static::lazy_static;
 
 use super::{
     eval::{evaluate, PIECE_VALUES},
     MOVE_GEN,
 };
 
-lazy_static! {
-    pub static ref STOPPED: AtomicBool = AtomicBool::new(false);
-}
+pub static STOPPED: AtomicBool = AtomicBool::new(false);
 
 pub trait Engine {
     fn search(&mut self, position: &Position, depth: usize) -> Option<BestMove>;
@@ -56,14 +51,14 @@ pub struct BestMove {
 }
 
 impl SearchData {
-    fn negamax(&mut self, node: &Position, mut alpha: i32, mut beta: i32, depth: usize) -> i32 {
+    fn negamax(&mut self, node: &mut Position, mut alpha: i32, mut beta: i32, depth: usize) -> i32 {
         if self.stopped() {
             return 0;
         }
         // Initialize PV table
         self.pv.init_length(self.ply);
 
-        self.nodes_evaluated += 1;
+        self.count_node();
 
         let repetitions = self.repetition_table.repetitions();
         if repetitions > 1 {
@@ -110,8 +105,11 @@ impl SearchData {
             .transposition_table
             .cashed_value(node, self.ply, pv_node, depth, alpha, beta);
 
+        // The cached score at root may come from an excluded MultiPV move.
         if let Some(cached_alpha) = cached_alpha {
-            return cached_alpha;
+            if !self.excludes_root_moves() {
+                return cached_alpha;
+            }
         }
 
         // Run quiescence search on horizon
@@ -121,7 +119,7 @@ impl SearchData {
 
         // Stop search if we are too deep
         if self.ply >= MAX_PLY {
-            return evaluate(node, &self.eval_table);
+            return evaluate(node, &self.eval_table, &self.pawn_table);
         }
 
         let in_check = MOVE_GEN.is_check(node);
@@ -132,7 +130,7 @@ impl SearchData {
         }
 
         // Statically evaluate current position. This is needed for pruning.
-        let static_eval = evaluate(node, &self.eval_table);
+        let static_eval = self.static_eval(node);
 
         // Razoring
         if let Some(score) = self.razoring(node, static_eval, alpha, beta, depth, in_check, pv_node)
@@ -140,33 +138,39 @@ impl SearchData {
             return score;
         }
 
-        // Generate legal moves for current position
-        let mut child_nodes = MOVE_GEN.generate_legal_moves(node);
-
-        // No need to search the only move in root position.
-        if self.is_root() && child_nodes.len() == 1 {
-            self.pv.push_pv_move(self.ply, child_nodes[0]);
-            stop();
-            return 0;
-        }
-
-        // Order moves by probability of being good in order to improve alpha-beta pruning.
-        self.order_moves(&mut child_nodes, node, best_move);
+        // Order moves by probability of being good in order to improve alpha-beta pruning. Moves
+        // are generated lazily, except for the root where some of them may be excluded.
+        let picker = if self.is_root() {
+            let mut child_nodes = MOVE_GEN.generate_legal_moves(node);
+            let only_move = child_nodes.len() == 1;
+            if self.excludes_root_moves() {
+                child_nodes.retain(|mv| {
+                    !self.excluded_moves.contains(mv)
+                        && (self.search_moves.is_empty() || self.search_moves.contains(mv))
+                });
+            }
 
-        // If there are no legal moves, we are in checkmate or stalemate
-        if child_nodes.is_empty() {
-            if in_check {
-                return -MATE_VALUE + self.ply as i32;
+            // No need to search the only legal move in root position, unless the search has to
+            // go on until `stop` anyway.
+            if only_move && self.excluded_moves.is_empty() && !self.time_control.is_infinite() {
+                self.pv.push_pv_move(self.ply, child_nodes[0]);
+                // A ponder search must go on until `ponderhit` or `stop`.
+                if !self.time_control.is_pondering() {
+                    stop();
+                }
+                return 0;
             }
 
-            return 0;
-        }
+            MovePicker::with_moves(&child_nodes, best_move, self.refutations())
+        } else {
+            MovePicker::new(best_move, self.refutations())
+        };
 
         // At this point we couldn't prune anything, so we need to start searching through legal
         // moves.
         self.search_move_list(
             node,
-            &child_nodes,
+            picker,
             alpha,
             beta,
             depth,
@@ -181,8 +185,8 @@ impl SearchData {
     #[allow(clippy::too_many_lines)]
     fn search_move_list(
         &mut self,
-        node: &Position,
-        move_list: &[Move],
+        node: &mut Position,
+        mut picker: MovePicker,
         mut alpha: i32,
         beta: i32,
         depth: usize,
@@ -197,26 +201,26 @@ impl SearchData {
         // Flag for transposition table indicating if we found exact score or not.
         let mut flag = HashFlag::ALPHA;
         let mut reduce = 0;
+        let mut legal_moves = 0;
 
-        for (moves_tried, child) in move_list.iter().enumerate() {
+        while let Some(mv) = picker.next(node, &self.history_moves) {
+            let child = &mv;
+            let moves_tried = legal_moves;
+            legal_moves += 1;
             let mut extend = 0;
 
-            // Make a move
-            let child_pos = {
-                let mut child_pos = node.clone();
-                let _ = child_pos.make_move(child);
-                child_pos
-            };
-            self.current_move = MaybeUninit::new(*child);
+            if self.is_root() {
+                self.report_current_move(*child, moves_tried + 1, depth);
+            }
 
-            let gives_check = MOVE_GEN.is_check(&child_pos);
+            let (piece, color) = node.piece_at(child.from()).expect("No piece found");
+            let irreversible = child.is_irreversible(node);
+            let gives_check = MOVE_GEN.gives_check(node, child);
 
             // Check extension. We don't extend if check is unsafe, that is oponnent can gain
             // material by series of captures. We check that using `static_exchange_evaluation`.
             if gives_check {
-                let value_of_moved_piece =
-                    PIECE_VALUES[node.piece_at(child.from()).unwrap().0 as usize];
-
+                let value_of_moved_piece = PIECE_VALUES[piece as usize];
                 let opponent_recapture_gain = see_move_done(node, child);
 
                 let is_safe_check = opponent_recapture_gain <= value_of_moved_piece;
@@ -245,6 +249,12 @@ impl SearchData {
                 break;
             }
 
+            // Make a move
+            let undo = node
+                .make_move(child)
+                .expect("BUG: The move picker only returns legal moves");
+            self.current_move = MaybeUninit::new(*child);
+
             // Late move pruning
             // We assume that moves that are far in the move list, are less likely to be good, so we prune them.
             // Not applicable in PV nodes, in check, in captures and in positions with mate score.
@@ -255,8 +265,7 @@ impl SearchData {
 
             // Check extension
             self.ply += 1;
-            self.repetition_table
-                .push(&child_pos, child.is_irreversible(node));
+            self.repetition_table.push(node, irreversible);
 
             // Calculate score with late move reduction
             //let score = -self.negamax(&child_pos, -beta, -alpha, depth - 1);
@@ -275,10 +284,11 @@ impl SearchData {
             }
 
             // Search move
-            let score = self.search_move(&child_pos, alpha, beta, depth, reduce, extend, pv_node);
+            let score = self.search_move(node, alpha, beta, depth, reduce, extend, pv_node);
 
             self.repetition_table.decrement();
             self.ply -= 1;
+            node.unmake_move(child, &undo);
 
             // Do not update anything if we are stopped
             if self.stopped() {
@@ -287,7 +297,6 @@ impl SearchData {
             // If we found better move, update alpha and best move
             if score > alpha {
                 if !child.is_capture() {
-                    let (piece, color) = node.piece_at(child.from()).expect("No piece found");
                     // Update history moves, so we can order moves better next time
                     self.history_moves[color as usize][piece as usize][child.to() as usize] +=
                         (depth * depth) as i32;
@@ -301,15 +310,18 @@ impl SearchData {
                 // Fail high
                 if score >= beta {
                     // Store beta cutoff in transposition table
-                    self.transposition_table.write(
-                        node.hash,
-                        beta,
-                        best_move,
-                        depth,
-                        self.ply,
-                        HashFlag::BETA,
-                        self.age,
-                    );
+                    if !self.excludes_root_moves() {
+                        self.transposition_table.write(
+                            node.hash,
+                            beta,
+                            best_move,
+                            depth,
+                            self.ply,
+                            HashFlag::BETA,
+                            self.age,
+                            Some(static_eval),
+                        );
+                    }
 
                     // Update move order
                     if !child.is_capture() {
@@ -339,9 +351,29 @@ impl SearchData {
             }
         }
 
-        // Store alpha cutoff in transposition table
-        self.transposition_table
-            .write(node.hash, alpha, best_move, depth, self.ply, flag, self.age);
+        // If there are no legal moves, we are in checkmate or stalemate
+        if legal_moves == 0 {
+            if in_check {
+                return -MATE_VALUE + self.ply as i32;
+            }
+
+            return 0;
+        }
+
+        // Store alpha cutoff in transposition table. Root results of later MultiPV lines aren't
+        // the real value of the position.
+        if !self.excludes_root_moves() {
+            self.transposition_table.write(
+                node.hash,
+                alpha,
+                best_move,
+                depth,
+                self.ply,
+                flag,
+                self.age,
+                Some(static_eval),
+            );
+        }
 
         alpha
     }
@@ -349,7 +381,7 @@ impl SearchData {
     #[allow(clippy::too_many_arguments)]
     fn search_move(
         &mut self,
-        child_pos: &Position,
+        child_pos: &mut Position,
         alpha: i32,
         beta: i32,
         depth: usize,
@@ -380,7 +412,7 @@ impl SearchData {
         score
     }
 
-    fn quiesce(&mut self, node: &Position, mut alpha: i32, beta: i32) -> i32 {
+    fn quiesce(&mut self, node: &mut Position, mut alpha: i32, beta: i32) -> i32 {
         if self.stopped() {
             return 0;
         }
@@ -393,7 +425,7 @@ impl SearchData {
             return cached_alpha;
         }
 
-        self.nodes_evaluated += 1;
+        self.count_node();
 
         let repetitions = self.repetition_table.repetitions();
         if repetitions > 1 {
@@ -411,10 +443,10 @@ impl SearchData {
         }
 
         if self.ply >= MAX_PLY {
-            return evaluate(node, &self.eval_table);
+            return evaluate(node, &self.eval_table, &self.pawn_table);
         }
 
-        let stand_pat = evaluate(node, &self.eval_table);
+        let stand_pat = self.static_eval(node);
 
         if stand_pat >= beta {
             return beta;
@@ -430,37 +462,21 @@ impl SearchData {
             alpha = stand_pat;
         }
 
-        let mut moves = MOVE_GEN.generate_legal_moves(node);
-
-        self.order_moves(&mut moves, node, None);
-
-        for mv in moves {
-            if !mv.is_capture() {
-                continue;
-            }
+        // Captures losing material are not searched.
+        let mut picker = MovePicker::captures();
 
-            if !mv.is_enpass_capture() {
-                let attacking_piece = node.piece_at(mv.from()).unwrap().0;
-                let captured_piece = node.piece_at(mv.to()).unwrap().0;
-
-                if PIECE_VALUES[attacking_piece as usize] > PIECE_VALUES[captured_piece as usize]
-                    && static_exchange_evaluation(node, &mv) < 0
-                {
-                    continue;
-                }
-            }
-
-            let child = {
-                let mut child = node.clone();
-                let _ = child.make_move(&mv);
-                child
-            };
+        while let Some(mv) = picker.next(node, &self.history_moves) {
+            let irreversible = mv.is_irreversible(node);
+            let undo = node
+                .make_move(&mv)
+                .expect("BUG: The move picker only returns legal moves");
 
             self.ply += 1;
-            self.repetition_table.push(&child, mv.is_irreversible(node));
-            let score = -self.quiesce(&child, -beta, -alpha);
+            self.repetition_table.push(node, irreversible);
+            let score = -self.quiesce(node, -beta, -alpha);
             self.repetition_table.decrement();
             self.ply -= 1;
+            node.unmake_move(&mv, &undo);
 
             if score > alpha {
                 alpha = score;
@@ -479,6 +495,22 @@ impl SearchData {
     pub const fn is_root(&self) -> bool {
         self.ply == 0
     }
+
+    /// Evaluation of the position, from the transposition table if it was stored there.
+    #[inline]
+    fn static_eval(&self, node: &Position) -> i32 {
+        self.transposition_table
+            .static_eval(node.hash)
+            .unwrap_or_else(|| evaluate(node, &self.eval_table, &self.pawn_table))
+    }
+
+    /// Whether this is the root of a `MultiPV` line after the first one, or of a search limited
+    /// by `searchmoves`.
+    #[must_use]
+    #[inline]
+    pub fn excludes_root_moves(&self) -> bool {
+        self.is_root() && (!self.excluded_moves.is_empty() || !self.search_moves.is_empty())
+    }
 }
 
 pub fn stop() {
@@ -1,7 +1,10 @@
 use std::{
     mem::MaybeUninit,
-    sync::{atomic::Ordering, Arc},
-    time::Instant,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
 use sdk::position::Position;
@@ -11,18 +14,25 @@ use super::{
     principal_variation::PrincipalVariation,
     utils::{
         repetition::Table,
-        time_control::{SearchOptions, TimeControl},
+        time_control::{SearchOptions, SearchStability, TimeControl},
     },
     MATE_VALUE,
 };
 
-use crate::engine::{eval::evaluation_table::EvaluationTable, search::STOPPED};
-use crate::engine::{options::Options, search::MAX_PLY};
+use crate::engine::{
+    eval::{evaluation_table::EvaluationTable, pawn_table::PawnTable},
+    search::{stop, STOPPED},
+};
+use crate::engine::{options::Options, search::MAX_PLY, MOVE_GEN};
 use move_gen::r#move::Move;
 pub const INF: i32 = 1_000_000;
 pub const DEFAULT_ALPHA: i32 = -INF;
 pub const DEFAULT_BETA: i32 = INF;
 pub const ASPIRATION_WINDOW_OFFSET: i32 = 50;
+/// Nodes between updates of the shared node counter and checks for periodic info.
+const NODES_PER_UPDATE: usize = 4096;
+/// Milliseconds between periodic info lines.
+const INFO_INTERVAL: isize = 1000;
 
 pub struct Search {
     pub time_control: Arc<TimeControl>,
@@ -31,14 +41,19 @@ pub struct Search {
     pub repetion_table: Table,
     pub transposition_table: Arc<TranspositionTable>,
     pub eval_table: Arc<EvaluationTable>,
+    pub pawn_table: Arc<PawnTable>,
+    pub search_moves: Vec<Move>,
     pub age: usize,
 }
 
 pub struct SearchThread {
     pub data: SearchData,
+    pub engine_options: Options,
     pub transposition_table: Arc<TranspositionTable>,
     pub eval_table: Arc<EvaluationTable>,
     pub depth: usize,
+    /// Stop once a mate in this many moves is found.
+    pub mate: Option<usize>,
     pub id: usize,
 }
 
@@ -54,9 +69,28 @@ pub struct SearchData {
     pub repetition_table: Table,
     pub transposition_table: Arc<TranspositionTable>,
     pub eval_table: Arc<EvaluationTable>,
+    pub pawn_table: Arc<PawnTable>,
     pub time_control: Arc<TimeControl>,
     pub age: usize,
     pub current_move: MaybeUninit<Move>,
+    /// Root moves of the `MultiPV` lines found so far in this iteration.
+    pub excluded_moves: Vec<Move>,
+    /// Root moves given with `go searchmoves`, empty when all moves are searched.
+    pub search_moves: Vec<Move>,
+    /// Nodes of all threads, sent per node with a node limit and in batches otherwise.
+    pub shared_nodes: Arc<AtomicUsize>,
+    pub node_limit: Option<usize>,
+    /// Part of `nodes_evaluated` already added to `shared_nodes`.
+    pub shared_nodes_sent: usize,
+    /// Highest ply reached in the current iteration.
+    pub seldepth: usize,
+    /// Only the prime thread reports the progress of the search.
+    pub is_prime_thread: bool,
+    pub engine_options: Options,
+    /// Depth of the current iteration.
+    pub root_depth: usize,
+    /// Search time of the last periodic info.
+    pub last_info: isize,
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -65,18 +99,22 @@ impl Search {
     pub fn new(
         options: SearchOptions,
         engine_options: Options,
-        is_white: bool,
+        time_control: Arc<TimeControl>,
         rep_table: Table,
         transposition_table: Arc<TranspositionTable>,
         eval_table: Arc<EvaluationTable>,
+        pawn_table: Arc<PawnTable>,
+        search_moves: Vec<Move>,
         age: usize,
     ) -> Self {
         Self {
-            time_control: Arc::new(options.time_control(is_white)),
+            time_control,
             options,
             repetion_table: rep_table,
             transposition_table,
             eval_table,
+            pawn_table,
+            search_moves,
             engine_options,
             age,
         }
@@ -85,6 +123,7 @@ impl Search {
     pub fn search(&mut self, position: &Position) {
         let mut threads = vec![];
         let threads_cnt = self.engine_options.threads;
+        let shared_nodes = Arc::new(AtomicUsize::new(0));
         for id in 0..threads_cnt {
             let data = SearchData {
                 nodes_evaluated: 0,
@@ -97,104 +136,215 @@ impl Search {
                 time_control: self.time_control.clone(),
                 age: self.age,
                 eval_table: self.eval_table.clone(),
+                pawn_table: self.pawn_table.clone(),
                 counter_moves: vec![vec![None; MAX_PLY]; 2],
                 pair_moves: vec![vec![None; MAX_PLY]; 2],
                 current_move: MaybeUninit::uninit(),
+                excluded_moves: Vec::new(),
+                search_moves: self.search_moves.clone(),
+                shared_nodes: shared_nodes.clone(),
+                node_limit: self.options.nodes,
+                shared_nodes_sent: 0,
+                seldepth: 0,
+                is_prime_thread: id == 0,
+                engine_options: self.engine_options,
+                root_depth: 0,
+                last_info: 0,
             };
 
             let mut thread = SearchThread {
                 data: data.clone(),
+                engine_options: self.engine_options,
                 transposition_table: data.transposition_table.clone(),
                 depth: self.options.depth.unwrap_or(150),
+                mate: self.options.mate,
                 id,
                 eval_table: self.eval_table.clone(),
             };
 
-            let pos = position.clone();
+            let mut pos = position.clone();
 
-            threads.push(std::thread::spawn(move || {
-                thread.go(&pos);
-            }));
+            threads.push(std::thread::spawn(move || thread.go(&mut pos)));
         }
 
+        let mut threads = threads.into_iter();
+        let best_move = threads.next().map(|prime| prime.join().unwrap());
+
+        // Helper threads are stopped and joined before `bestmove`, so that the search is over
+        // once the GUI sees it and may start the next one.
+        stop();
         for thread in threads {
             thread.join().unwrap();
         }
+
+        print_best_move(best_move.flatten(), self.engine_options.chess960);
     }
 }
 
+/// A finished `MultiPV` line of one iteration.
+struct RootLine {
+    score: i32,
+    mv: Option<Move>,
+    ponder: Option<Move>,
+    pv: String,
+}
+
 impl SearchThread {
-    pub fn go(&mut self, position: &Position) {
+    /// Searches until stopped or out of depth or time. The prime thread returns the best move
+    /// and the move to ponder on, once it may be sent.
+    pub fn go(&mut self, position: &mut Position) -> Option<(Move, Option<Move>)> {
         let is_prime_thread = self.id == 0;
-        let (mut alpha, mut beta) = (DEFAULT_ALPHA, DEFAULT_BETA);
+        let chess960 = self.engine_options.chess960;
+        let legal_moves = MOVE_GEN.generate_legal_moves(position).len();
+        let root_moves = if self.data.search_moves.is_empty() {
+            legal_moves
+        } else {
+            self.data.search_moves.len()
+        };
+        // Helper threads only fill the transposition table, so they search a single line.
+        let lines = if is_prime_thread {
+            self.engine_options.multi_pv.min(root_moves).max(1)
+        } else {
+            1
+        };
+        // Aspiration window of every line
+        let mut windows = vec![(DEFAULT_ALPHA, DEFAULT_BETA); lines];
 
         let mut best_move = None;
+        let mut stability = SearchStability::default();
         for depth in 1..=self.depth {
             if self.data.stopped() {
                 break;
             }
+            let iteration_start = Instant::now();
             self.data.reset();
-            let mut best_score = self.data.negamax(position, alpha, beta, depth);
+            self.data.root_depth = depth;
+            let mut root_lines = Vec::with_capacity(lines);
 
-            // Try full search if aspiration window failed
-            if best_score <= alpha || best_score >= beta {
-                alpha = DEFAULT_ALPHA;
-                beta = DEFAULT_BETA;
-                best_score = self.data.negamax(position, alpha, beta, depth);
-            }
+            for (line, (alpha, beta)) in windows.iter_mut().enumerate() {
+                self.data.pv = PrincipalVariation::default();
+                let mut score = self.data.negamax(position, *alpha, *beta, depth);
 
-            // Adjust aspiration window
-            alpha = best_score - ASPIRATION_WINDOW_OFFSET;
-            beta = best_score + ASPIRATION_WINDOW_OFFSET;
+                // Try full search if aspiration window failed
+                if score <= *alpha || score >= *beta {
+                    if is_prime_thread && !self.data.stopped() {
+                        let bound = if score <= *alpha {
+                            "upperbound"
+                        } else {
+                            "lowerbound"
+                        };
+                        self.data.report_bound(line + 1, score, bound);
+                    }
+                    *alpha = DEFAULT_ALPHA;
+                    *beta = DEFAULT_BETA;
+                    score = self.data.negamax(position, *alpha, *beta, depth);
+                }
+
+                // Adjust aspiration window
+                *alpha = score - ASPIRATION_WINDOW_OFFSET;
+                *beta = score + ASPIRATION_WINDOW_OFFSET;
+
+                // Only the first line is reported when the search is interrupted.
+                let mv = self.data.pv.best();
+                if line > 0 && (self.data.stopped() || mv.is_none()) {
+                    break;
+                }
+                root_lines.push(RootLine {
+                    score,
+                    mv,
+                    ponder: self.data.pv.ponder(),
+                    pv: self.data.pv.to_uci(chess960),
+                });
+
+                match mv {
+                    Some(mv) if !self.data.stopped() => self.data.excluded_moves.push(mv),
+                    _ => break,
+                }
+            }
+            self.data.excluded_moves.clear();
+            root_lines.sort_by_key(|line| -line.score);
 
             if is_prime_thread {
-                let current_nodes_count = self.data.nodes_evaluated;
-
-                let time = self.data.time_control.search_time(Instant::now());
-
-                let nps = if time == 0 {
-                    20000
-                } else {
-                    (current_nodes_count as f64 / (time as f64 / 1000.0)) as usize
-                };
-
-                let score_str = mate_score(best_score).map_or_else(
-                    || format!("cp {best_score}"),
-                    |score| format!("mate {score}"),
-                );
-
-                // Fix lichess draw bug
-                println!(
-                    "info score {} depth {} nodes {} nps {} time {} pv {}",
-                    score_str,
-                    depth,
-                    current_nodes_count,
-                    nps,
-                    time,
-                    self.data.pv.to_string()
-                );
+                self.print_lines(depth, &root_lines);
 
                 if self.data.stopped() {
                     break;
                 }
             }
 
-            if self.data.pv.best().is_some() && !self.data.stopped() {
-                best_move = self.data.pv.best();
-            }
-        }
-        if is_prime_thread {
-            if let Some(best_move) = best_move {
-                println!("bestmove {best_move}");
-            } else {
-                // Log null move, just to satisfy the protocol
-                if let Some(best) = self.data.pv.best() {
-                    println!("bestmove {best}");
-                } else {
-                    println!("bestmove a1a1");
+            if let Some(line) = root_lines.first() {
+                if let (Some(mv), false) = (line.mv, self.data.stopped()) {
+                    best_move = Some((mv, line.ponder));
+                    stability.update(mv, line.score);
                 }
             }
+
+            // The only move is already known, deeper iterations would only wait for `ponderhit`.
+            if legal_moves == 1 && !self.data.time_control.is_infinite() {
+                break;
+            }
+
+            // Helper threads are stopped together with the prime thread.
+            let last_iteration = iteration_start.elapsed().as_millis() as isize;
+            if is_prime_thread
+                && (self.mate_found(&root_lines)
+                    || self
+                        .data
+                        .time_control
+                        .should_stop_iterating(last_iteration, &stability))
+            {
+                stop();
+                break;
+            }
+        }
+        if !is_prime_thread {
+            return None;
         }
+
+        // `bestmove` may not be sent while pondering, even if the search is finished.
+        while self.data.time_control.is_pondering() && !STOPPED.load(Ordering::Relaxed) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        best_move.or_else(|| self.data.pv.best().map(|mv| (mv, self.data.pv.ponder())))
+    }
+}
+
+impl SearchThread {
+    fn print_lines(&mut self, depth: usize, root_lines: &[RootLine]) {
+        let stats = self.data.info_stats();
+        self.data.last_info = self.data.time_control.search_time(Instant::now());
+
+        for (idx, line) in root_lines.iter().enumerate() {
+            println!(
+                "info depth {depth} multipv {} score {} {stats} pv {}",
+                idx + 1,
+                score_to_uci(line.score),
+                line.pv
+            );
+        }
+    }
+
+    fn mate_found(&self, root_lines: &[RootLine]) -> bool {
+        let mate_in = root_lines.first().and_then(|line| mate_score(line.score));
+
+        match (mate_in, self.mate) {
+            (Some(mate_in), Some(mate)) => mate_in > 0 && mate_in as usize <= mate,
+            _ => false,
+        }
+    }
+}
+
+fn print_best_move(best_move: Option<(Move, Option<Move>)>, chess960: bool) {
+    match best_move {
+        Some((best, Some(ponder))) => println!(
+            "bestmove {} ponder {}",
+            best.to_uci(chess960),
+            ponder.to_uci(chess960)
+        ),
+        Some((best, None)) => println!("bestmove {}", best.to_uci(chess960)),
+        // Log null move, just to satisfy the protocol
+        None => println!("bestmove a1a1"),
     }
 }
 
@@ -202,6 +352,7 @@ impl SearchData {
     pub fn reset(&mut self) {
         //*self.nodes_evaluated.lock().unwrap() = 0;
         self.ply = 0;
+        self.seldepth = 0;
         self.killer_moves = vec![vec![None; MAX_PLY]; 2];
         self.history_moves = vec![vec![vec![0; 64]; 6]; 2];
         self.pv = PrincipalVariation::default();
@@ -210,8 +361,95 @@ impl SearchData {
 
     #[must_use]
     pub fn stopped(&self) -> bool {
-        self.time_control.is_over() || STOPPED.load(Ordering::Relaxed)
+        self.time_control.is_over()
+            || STOPPED.load(Ordering::Relaxed)
+            || self
+                .node_limit
+                .is_some_and(|limit| self.shared_nodes.load(Ordering::Relaxed) >= limit)
     }
+
+    #[inline]
+    pub fn count_node(&mut self) {
+        self.nodes_evaluated += 1;
+        self.seldepth = self.seldepth.max(self.ply);
+
+        // Node limits are checked exactly, otherwise nodes are sent in batches.
+        if self.node_limit.is_some() {
+            self.send_nodes();
+        }
+        if self.nodes_evaluated.is_multiple_of(NODES_PER_UPDATE) {
+            self.send_nodes();
+            if self.is_prime_thread {
+                self.report_progress();
+            }
+        }
+    }
+
+    fn send_nodes(&mut self) {
+        self.shared_nodes.fetch_add(
+            self.nodes_evaluated - self.shared_nodes_sent,
+            Ordering::Relaxed,
+        );
+        self.shared_nodes_sent = self.nodes_evaluated;
+    }
+
+    /// Nodes of all threads, including the ones this thread didn't send yet.
+    #[must_use]
+    pub fn total_nodes(&self) -> usize {
+        self.shared_nodes.load(Ordering::Relaxed) + self.nodes_evaluated - self.shared_nodes_sent
+    }
+
+    /// Statistics shared by all info lines, from `seldepth` to `time`.
+    fn info_stats(&self) -> String {
+        let time = self.time_control.search_time(Instant::now());
+        let nodes = self.total_nodes();
+
+        format!(
+            "seldepth {} nodes {} nps {} hashfull {} tbhits 0 time {}",
+            self.seldepth,
+            nodes,
+            nodes as u128 * 1000 / time.max(1) as u128,
+            self.transposition_table.hashfull(self.age),
+            time
+        )
+    }
+
+    /// Prints an info line once in a while, so that GUIs don't look frozen during long
+    /// iterations.
+    fn report_progress(&mut self) {
+        let time = self.time_control.search_time(Instant::now());
+        if time - self.last_info >= INFO_INTERVAL {
+            self.last_info = time;
+            println!("info depth {} {}", self.root_depth, self.info_stats());
+        }
+    }
+
+    /// Prints the root move being searched, once the search takes long enough for it to matter.
+    pub fn report_current_move(&self, mv: Move, number: usize, depth: usize) {
+        if self.is_prime_thread && self.time_control.search_time(Instant::now()) >= INFO_INTERVAL {
+            println!(
+                "info depth {depth} currmove {} currmovenumber {number}",
+                mv.to_uci(self.engine_options.chess960)
+            );
+        }
+    }
+
+    /// Prints the score of a line which failed the aspiration window. Short iterations don't
+    /// report it, as the line is searched again right away.
+    fn report_bound(&self, multipv: usize, score: i32, bound: &str) {
+        if self.time_control.search_time(Instant::now()) >= INFO_INTERVAL {
+            println!(
+                "info depth {} multipv {multipv} score {} {bound} {}",
+                self.root_depth,
+                score_to_uci(score),
+                self.info_stats()
+            );
+        }
+    }
+}
+
+fn score_to_uci(score: i32) -> String {
+    mate_score(score).map_or_else(|| format!("cp {score}"), |score| format!("mate {score}"))
 }
 
 fn mate_score(score: i32) -> Option<i32> {
@@ -38,18 +38,24 @@ impl PrincipalVariation {
         self.table[0][0]
     }
 
+    /// Expected reply to the best move, for `bestmove ... ponder`.
+    #[must_use]
+    pub fn ponder(&self) -> Option<Move> {
+        (self.length[0] > 1).then_some(self.table[0][1]).flatten()
+    }
+
     #[must_use]
     pub fn is_only_legal_move(&self) -> bool {
         self.length[0] == 1
     }
-}
 
-impl ToString for PrincipalVariation {
-    fn to_string(&self) -> String {
+    #[must_use]
+    pub fn to_uci(&self, chess960: bool) -> String {
         let mut pv = String::new();
 
         for i in 0..self.length[0] {
-            pv.push_str(&format!("{} ", self.table[0][i].unwrap()));
+            pv.push_str(&self.table[0][i].unwrap().to_uci(chess960));
+            pv.push(' ');
         }
 
         pv
@@ -1,36 +1,157 @@
-use std::time::Instant;
+use std::{
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::Instant,
+};
 
 use derivative::Derivative;
+use move_gen::r#move::Move;
+
+/// Moves to go assumed in sudden death games.
+const DEFAULT_MOVES_TO_GO: isize = 30;
+/// How many times longer than the soft limit a single move may take.
+const HARD_LIMIT_FACTOR: isize = 4;
+/// Expected time of the next iteration relative to the last one.
+const ITERATION_GROWTH: isize = 2;
 
 #[derive(Debug)]
 pub struct TimeControl {
     start_time: Instant,
-    limit: Option<isize>,
+    /// No new iteration is started after this time, scaled by the stability of the search.
+    soft_limit: Option<isize>,
+    /// The search is stopped at this time, no matter what.
+    hard_limit: Option<isize>,
     is_infinite: bool,
+    /// While set the search ignores the limits, until `ponderhit` or `stop`.
+    pondering: AtomicBool,
+    /// Milliseconds after `start_time` at which the limits started counting.
+    limit_start: AtomicUsize,
 }
 
 impl TimeControl {
+    fn new(
+        soft_limit: Option<isize>,
+        hard_limit: Option<isize>,
+        is_infinite: bool,
+        pondering: bool,
+    ) -> Self {
+        TimeControl {
+            start_time: Instant::now(),
+            soft_limit,
+            hard_limit,
+            is_infinite,
+            pondering: AtomicBool::new(pondering),
+            limit_start: AtomicUsize::new(0),
+        }
+    }
+
     #[must_use]
     pub fn search_time(&self, now: Instant) -> isize {
         now.duration_since(self.start_time).as_millis() as isize
     }
 
+    /// Time spent since the limits started counting.
+    fn limited_time(&self, now: Instant) -> isize {
+        self.search_time(now) - self.limit_start.load(Ordering::Relaxed) as isize
+    }
+
     #[must_use]
     pub fn remaining_time(&self, now: Instant) -> isize {
-        if self.is_infinite {
+        if self.is_infinite || self.is_pondering() {
             return isize::MAX;
         }
-        self.limit.unwrap_or_default() - self.search_time(now)
+
+        self.hard_limit.unwrap_or_default() - self.limited_time(now)
     }
 
     #[must_use]
     pub fn is_over(&self) -> bool {
-        if self.is_infinite {
+        if self.is_infinite || self.is_pondering() {
             return false;
         }
 
         self.remaining_time(Instant::now()) <= 0
     }
+
+    /// Whether the iterative deepening should stop after an iteration which took
+    /// `last_iteration` milliseconds. It stops when the scaled soft limit is reached, or when the
+    /// next iteration is not expected to finish before the hard limit.
+    #[must_use]
+    pub fn should_stop_iterating(
+        &self,
+        last_iteration: isize,
+        stability: &SearchStability,
+    ) -> bool {
+        if self.is_infinite || self.is_pondering() {
+            return false;
+        }
+
+        let elapsed = self.limited_time(Instant::now());
+        let soft_limit = self.soft_limit.map_or(isize::MAX, |limit| {
+            (limit as f64 * stability.scale()) as isize
+        });
+
+        elapsed >= soft_limit
+            || elapsed + last_iteration * ITERATION_GROWTH > self.hard_limit.unwrap_or_default()
+    }
+
+    /// The search has no time limit and only ends at its depth or on `stop`.
+    #[must_use]
+    pub fn is_infinite(&self) -> bool {
+        self.is_infinite
+    }
+
+    #[must_use]
+    pub fn is_pondering(&self) -> bool {
+        self.pondering.load(Ordering::Relaxed)
+    }
+
+    /// The opponent played the expected move, so the search continues on the real clock,
+    /// which starts counting now.
+    pub fn ponderhit(&self) {
+        let elapsed = self.search_time(Instant::now()) as usize;
+        self.limit_start.store(elapsed, Ordering::Relaxed);
+        self.pondering.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Tracks how the result of the iterative deepening changes, to spend more time on unclear
+/// positions and less on clear ones.
+#[derive(Debug, Default)]
+pub struct SearchStability {
+    best_move: Option<Move>,
+    /// Number of iterations in a row with the same best move.
+    stable_iterations: usize,
+    last_score: Option<i32>,
+    /// How much the score dropped in the last iteration.
+    score_drop: i32,
+}
+
+impl SearchStability {
+    pub fn update(&mut self, best_move: Move, score: i32) {
+        if self.best_move == Some(best_move) {
+            self.stable_iterations += 1;
+        } else {
+            self.stable_iterations = 0;
+        }
+        self.score_drop = self.last_score.map_or(0, |last| (last - score).max(0));
+        self.best_move = Some(best_move);
+        self.last_score = Some(score);
+    }
+
+    /// Factor of the soft limit.
+    #[must_use]
+    pub fn scale(&self) -> f64 {
+        const STABILITY_SCALE: [f64; 6] = [1.6, 1.3, 1.1, 0.95, 0.8, 0.7];
+
+        let stability = STABILITY_SCALE[self.stable_iterations.min(STABILITY_SCALE.len() - 1)];
+        let score_drop = match self.score_drop {
+            0..20 => 1.0,
+            20..50 => 1.2,
+            _ => 1.5,
+        };
+
+        stability * score_drop
+    }
 }
 
 #[derive(Derivative, Debug)]
@@ -38,6 +159,8 @@ impl TimeControl {
 pub struct SearchOptions {
     pub depth: Option<usize>,
     pub nodes: Option<usize>,
+    /// Search for a mate in this many moves.
+    pub mate: Option<usize>,
     pub movetime: Option<isize>,
     pub infinite: bool,
     pub ponder: bool,
@@ -46,63 +169,109 @@ pub struct SearchOptions {
     pub winc: Option<isize>,
     pub binc: Option<isize>,
     pub movestogo: Option<isize>,
+    /// Moves in UCI notation the root search is limited to.
+    pub search_moves: Vec<String>,
+    /// Count the leaf nodes this many plies deep instead of searching.
+    pub perft: Option<usize>,
 }
 
 impl SearchOptions {
     #[must_use]
-    pub fn time_control(&self, is_white: bool) -> TimeControl {
-        if self.infinite {
-            return TimeControl {
-                start_time: Instant::now(),
-                limit: None,
-                is_infinite: true,
-            };
-        }
+    pub fn time_control(&self, is_white: bool, move_overhead: usize) -> TimeControl {
+        let move_overhead = move_overhead as isize;
 
-        if self.movetime.is_some() {
-            return TimeControl {
-                start_time: Instant::now(),
-                limit: self.movetime,
-                is_infinite: false,
-            };
+        if self.infinite {
+            return TimeControl::new(None, None, true, self.ponder);
         }
 
-        if (is_white && self.wtime.is_none()) || (!is_white && self.btime.is_none()) {
-            return TimeControl {
-                start_time: Instant::now(),
-                limit: None,
-                is_infinite: true,
-            };
+        if let Some(movetime) = self.movetime {
+            // Iterations are still not started when they can't finish in time.
+            let limit = (movetime - move_overhead).max(1);
+            return TimeControl::new(Some(limit), Some(limit), false, self.ponder);
         }
 
-        let _increment = if is_white {
-            self.winc.unwrap_or(0)
+        let (time_left, increment) = if is_white {
+            (self.wtime, self.winc.unwrap_or(0))
         } else {
-            self.binc.unwrap_or(0)
+            (self.btime, self.binc.unwrap_or(0))
         };
 
-        let time_left = if is_white {
-            self.wtime.unwrap_or(0)
-        } else {
-            self.btime.unwrap_or(0)
+        let Some(time_left) = time_left else {
+            return TimeControl::new(None, None, true, self.ponder);
         };
 
-        let moves_to_go = self.movestogo.unwrap_or(20).max(1);
+        let available = (time_left - move_overhead).max(1);
+        let moves_to_go = self.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).clamp(1, 50);
 
-        let limit = time_left / moves_to_go;
+        // Spend the average time per move plus most of the increment, but always keep a reserve
+        // so that the hard limit can be reached a few moves in a row.
+        let hard_limit = (available * 3 / 4).max(1);
+        let soft_limit = (available / moves_to_go + increment * 3 / 4)
+            .min(hard_limit / 2)
+            .max(1);
+        let hard_limit = (soft_limit * HARD_LIMIT_FACTOR).min(hard_limit);
 
-        if limit <= 0 {
-            return TimeControl {
-                start_time: Instant::now(),
-                limit: None,
-                is_infinite: false,
-            };
+        TimeControl::new(Some(soft_limit), Some(hard_limit), false, self.ponder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(options: &SearchOptions) -> (isize, isize) {
+        let time_control = options.time_control(true, 10);
+        (
+            time_control.soft_limit.unwrap(),
+            time_control.hard_limit.unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_time_limits() {
+        // 1+0 bullet, from the first move until the flag is about to fall.
+        for wtime in [60_000, 5_000, 500, 50, 5] {
+            let (soft, hard) = limits(&SearchOptions {
+                wtime: Some(wtime),
+                ..Default::default()
+            });
+            assert!(0 < soft && soft <= hard);
+            assert!(hard <= (wtime - 10).max(1));
         }
 
-        TimeControl {
-            start_time: Instant::now(),
-            limit: Some(limit),
-            is_infinite: false,
+        // The increment is used.
+        let (soft, _) = limits(&SearchOptions {
+            wtime: Some(60_000),
+            winc: Some(1_000),
+            ..Default::default()
+        });
+        assert_eq!(soft, 59_990 / 30 + 750);
+
+        // The last move before the time control may use more of the clock.
+        let (soft, hard) = limits(&SearchOptions {
+            wtime: Some(10_000),
+            movestogo: Some(1),
+            ..Default::default()
+        });
+        assert_eq!((soft, hard), (3_746, 7_492));
+
+        assert!(SearchOptions::default().time_control(true, 10).is_infinite);
+    }
+
+    #[test]
+    fn test_search_stability() {
+        let mv = Move::null();
+        let mut stability = SearchStability::default();
+        stability.update(mv, 30);
+        let unstable = stability.scale();
+
+        for _ in 0..5 {
+            stability.update(mv, 30);
         }
+        assert!(stability.scale() < 1.0);
+        assert!(stability.scale() < unstable);
+
+        stability.update(mv, -40);
+        assert!(stability.scale() > 1.0);
     }
 }
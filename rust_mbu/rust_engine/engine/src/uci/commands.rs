@@ -10,6 +10,7 @@ pub enum Command {
     SetOption(String, Option<String>),
     Go(SearchOptions),
     Stop,
+    PonderHit,
     UciNewGame,
     IsReady,
     Quit,
@@ -24,6 +25,7 @@ impl FromStr for Command {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "stop" => Ok(Command::Stop),
+            "ponderhit" => Ok(Command::PonderHit),
             "isready" => Ok(Command::IsReady),
             "uci" => Ok(Command::Uci),
             "quit" => Ok(Command::Quit),
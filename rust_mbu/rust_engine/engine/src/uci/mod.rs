@@ -16,7 +16,7 @@ pub type Result<T> = anyhow::Result<T>;
 /// # Panics
 /// Panics if the command is invalid
 pub fn start() {
-    let tx = Engine::start_loop_thread();
+    let (tx, engine_thread) = Engine::start_loop_thread();
 
     println!("ready");
 
@@ -39,12 +39,16 @@ pub fn start() {
             "simulate" => Ok(Command::Simulate(
                 args.into_iter().map(ToString::to_string).collect_vec(),
             )),
-            "quit" => return,
+            "quit" => {
+                tx.send(Command::Quit).expect("Failed to send command");
+                engine_thread.join().expect("Engine thread panicked");
+                return;
+            }
             any => Command::from_str(any).map_err(|_| anyhow!("Unknown command {any}")),
         };
         match command {
             Ok(command) => tx.send(command).expect("Failed to send command"),
-            Err(e) => println!("{e}"),
+            Err(e) => println!("info string {e}"),
         }
     }
 }
@@ -56,12 +60,14 @@ fn parse_position(args: &[&str]) -> Result<Command> {
 
     let (pos, idx) = if args[0] == "startpos" {
         (Position::default(), 1)
+    } else if args[0] != "fen" {
+        return Err(anyhow!("Expected 'startpos' or 'fen'"));
     } else {
         let mut iter = args.iter().skip(1).take_while(|s| **s != "moves");
         let idx = iter.clone().count() + 1;
         let fen = iter.join(" ");
 
-        let pos = Position::from_fen(fen)?;
+        let pos = Position::from_fen_lenient(fen)?;
 
         (pos, idx)
     };
@@ -79,6 +85,22 @@ fn parse_position(args: &[&str]) -> Result<Command> {
     Ok(Command::Position(pos, moves))
 }
 
+const GO_TOKENS: [&str; 13] = [
+    "depth",
+    "nodes",
+    "mate",
+    "movetime",
+    "infinite",
+    "ponder",
+    "wtime",
+    "btime",
+    "winc",
+    "binc",
+    "movestogo",
+    "searchmoves",
+    "perft",
+];
+
 fn parse_go(args: &[&str]) -> Result<Command> {
     let mut idx = 0;
     let mut search_options = SearchOptions::default();
@@ -103,13 +125,13 @@ fn parse_go(args: &[&str]) -> Result<Command> {
             ($field:ident) => {{
                 search_options.$field = true;
                 idx += 1;
-                continue;
             }};
         }
 
         match token {
             "depth" => parse_set_field!(depth),
             "nodes" => parse_set_field!(nodes),
+            "mate" => parse_set_field!(mate),
             "movetime" => parse_set_field!(movetime),
             "infinite" => parse_flag_field!(infinite),
             "ponder" => parse_flag_field!(ponder),
@@ -118,6 +140,17 @@ fn parse_go(args: &[&str]) -> Result<Command> {
             "winc" => parse_set_field!(winc),
             "binc" => parse_set_field!(binc),
             "movestogo" => parse_set_field!(movestogo),
+            "perft" => parse_set_field!(perft),
+            "searchmoves" => {
+                // Moves last until the next token of `go`.
+                let moves = args[idx + 1..]
+                    .iter()
+                    .take_while(|token| !GO_TOKENS.contains(token))
+                    .map(ToString::to_string)
+                    .collect_vec();
+                idx += moves.len() + 1;
+                search_options.search_moves = moves;
+            }
             _ => return Err(anyhow!("Unknown token {token}")),
         }
     }
@@ -145,7 +178,37 @@ pub fn parse_set_option(args: &[&str]) -> Result<Command> {
     let value_idx = args.iter().take_while(|s| **s != "value").count();
 
     let name = args[1..value_idx].join(" ");
-    let value = args.get(value_idx + 1).map(ToString::to_string);
+    // Values such as file paths may contain spaces.
+    let value = (value_idx + 1 < args.len()).then(|| args[value_idx + 1..].join(" "));
 
     Ok(Command::SetOption(name, value))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_go_limits() {
+        let Ok(Command::Go(options)) =
+            parse_go(&["searchmoves", "e2e4", "d2d4", "nodes", "1000", "mate", "3"])
+        else {
+            panic!("Expected go command");
+        };
+
+        assert_eq!(options.search_moves, vec!["e2e4", "d2d4"]);
+        assert_eq!(options.nodes, Some(1000));
+        assert_eq!(options.mate, Some(3));
+        assert!(parse_go(&["mate"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_go_perft() {
+        let Ok(Command::Go(options)) = parse_go(&["perft", "4"]) else {
+            panic!("Expected go command");
+        };
+
+        assert_eq!(options.perft, Some(4));
+        assert!(parse_go(&["perft", "x"]).is_err());
+    }
+}
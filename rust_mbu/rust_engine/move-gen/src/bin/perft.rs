@@ -0,0 +1,106 @@
+//! Counts the leaf nodes of the move tree, for validating the move generator.
+//!
+//! `perft [--divide] [--threads N] [--hash MB] [--no-bulk] [--chess960] DEPTH [FEN]`
+//!
+//! Without a FEN the initial position is used. `--divide` prints the count below each root move.
+
+use std::{process::ExitCode, time::Instant};
+
+use move_gen::{
+    generators::movegen::MoveGen,
+    utils::perft::{divide, PerftOptions},
+};
+use sdk::{fen::Fen, position::Position};
+
+const USAGE: &str = "Usage: perft [--divide] [--threads N] [--hash MB] [--no-bulk] [--chess960] \
+                     DEPTH [FEN]";
+
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+struct Args {
+    options: PerftOptions,
+    divide: bool,
+    chess960: bool,
+    depth: usize,
+    fen: String,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut options = PerftOptions::default();
+    let mut divide = false;
+    let mut chess960 = false;
+    let mut positional = Vec::new();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("Missing value for {arg}"));
+        match arg.as_str() {
+            "--divide" => divide = true,
+            "--threads" => options.threads = parse_number(&value()?)?,
+            "--hash" => options.hash_mb = parse_number(&value()?)?,
+            "--no-bulk" => options.bulk = false,
+            "--chess960" => chess960 = true,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {arg}")),
+            _ => positional.push(arg),
+        }
+    }
+
+    let (depth, fen) = positional.split_first().ok_or(USAGE)?;
+
+    Ok(Args {
+        options,
+        divide,
+        chess960,
+        depth: parse_number(depth)?,
+        fen: if fen.is_empty() {
+            START_FEN.to_string()
+        } else {
+            fen.join(" ")
+        },
+    })
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid number {value}"))
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let pos = Position::from_fen(args.fen.clone())
+        .map_err(|e| format!("Invalid FEN {}: {e}", args.fen))?;
+    let movegen = MoveGen::new();
+
+    let start = Instant::now();
+    let nodes: usize = if args.depth == 0 {
+        1
+    } else {
+        let counts = divide(&movegen, &pos, args.depth, &args.options);
+        if args.divide {
+            for (mv, nodes) in &counts {
+                println!("{}: {nodes}", mv.to_uci(args.chess960));
+            }
+            println!();
+        }
+
+        counts.iter().map(|(_, nodes)| nodes).sum()
+    };
+    let elapsed = start.elapsed();
+
+    println!("Nodes: {nodes}");
+    println!(
+        "Time: {} ms, {:.0} nodes/s",
+        elapsed.as_millis(),
+        nodes as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+    );
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match parse_args(std::env::args().skip(1)).and_then(run) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -11,7 +11,7 @@ use core::array;
 
 use crate::{
     lookup::{load_lookup_tables, LookupTables, MagicEntry},
-    r#move::{MakeMove, Move, MoveKind},
+    r#move::{move_list::MoveList, MakeMove, Move, MoveKind},
     xray::XRayGenerator,
 };
 
@@ -21,6 +21,9 @@ use super::pieces::{
     slider_generator::SliderMoveGenerator,
 };
 
+/// Upper bound on the number of legal moves in any position (the known maximum is 218).
+pub const MAX_MOVES: usize = 256;
+
 pub struct MoveGen {
     pub lookups: LookupTables,
 }
@@ -110,76 +113,314 @@ impl MoveGen {
         attacked_bb.count() == 2
     }
 
-    pub fn generate_legal_moves<'a>(&'a self, pos: &'a Position) -> ArrayVec<Move, 128> {
-        let friendly_occ = pos.occupation(&pos.turn);
-        let enemy_occ = pos.occupation(&pos.enemy());
-        let pinned_pieces = self.pinned_pieces(pos, pos.turn);
-        let king_square = pos.pieces[pos.turn as usize][Piece::King as usize].msb();
+    /// Generates all legal moves.
+    pub fn generate_legal_moves(&self, pos: &Position) -> MoveList {
+        self.generate(pos, MoveFilter::All)
+    }
+
+    /// Generates legal captures, including capturing promotions, and promotions to a queen.
+    pub fn generate_captures(&self, pos: &Position) -> MoveList {
+        self.generate(pos, MoveFilter::Captures)
+    }
+
+    /// Generates the legal moves left out by `generate_captures`: moves to empty squares,
+    /// castling and underpromotions without a capture.
+    pub fn generate_quiets(&self, pos: &Position) -> MoveList {
+        self.generate(pos, MoveFilter::Quiets)
+    }
 
-        let pawn_quiet_moves =
-            self.generate_pawn_moves(pos, friendly_occ, enemy_occ, pinned_pieces, king_square);
-        let pawn_capturing_moves =
-            self.generate_pawn_attacks(pos, friendly_occ, enemy_occ, pinned_pieces, king_square);
-        let knight_moves = self.generate_knight_moves(pos, friendly_occ, enemy_occ, pinned_pieces);
-        let slider_moves =
-            self.generate_slider_moves(pos, friendly_occ, enemy_occ, pinned_pieces, king_square);
-        let king_moves = self.generate_king_moves(pos, friendly_occ, enemy_occ, pinned_pieces);
-        let castling_moves =
-            self.generate_all_castlings(pos, friendly_occ, enemy_occ, pinned_pieces);
-
-        let mut attackers_to_king =
-            self.attacks_to_square(pos, king_square, pos.enemy(), pos.occupied);
-
-        let non_king_moves = pawn_capturing_moves
-            .chain(knight_moves)
-            .chain(slider_moves)
-            .chain(pawn_quiet_moves)
-            .chain(castling_moves);
-
-        if attackers_to_king.is_empty() {
-            return king_moves.chain(non_king_moves).collect();
+    /// Generates legal moves getting out of check. There are none if the side to move is not
+    /// in check.
+    pub fn generate_evasions(&self, pos: &Position) -> MoveList {
+        if self.is_check(pos) {
+            self.generate(pos, MoveFilter::All)
+        } else {
+            MoveList::new()
         }
-        let attacker_sq = attackers_to_king.pop_lsb();
-
-        if attackers_to_king.is_empty() {
-            let slider = match pos.piece_at(attacker_sq).unwrap().0 {
-                Piece::Rook => Some(Slider::Rook),
-                Piece::Bishop => Some(Slider::Bishop),
-                Piece::Queen => Some(Slider::Queen),
-                _ => None,
-            };
+    }
 
-            let can_enpass_kill_attacker = if let Some(en_passant) = pos.en_passant {
-                let offset = if pos.turn == Color::White { -1 } else { 1 };
+    /// Generates the moves of `generate_quiets` which give check.
+    pub fn generate_quiet_checks(&self, pos: &Position) -> MoveList {
+        let mut moves = self.generate(pos, MoveFilter::Quiets);
+        moves.retain(|mv| self.gives_check(pos, mv));
+
+        moves
+    }
+
+    /// Checks whether the legal move `mv` puts the enemy king in check.
+    pub fn gives_check(&self, pos: &Position, mv: &Move) -> bool {
+        // Castling, promotions and en passant move more than one piece, so they are checked on
+        // the position after the move.
+        if mv.promotion().is_some() || matches!(mv.kind(), MoveKind::Castling | MoveKind::EnPassant)
+        {
+            let mut child = pos.clone();
+            return child.make_move(mv).is_ok() && self.is_check(&child);
+        }
+
+        let color = pos.turn;
+        let pieces = pos.pieces[color as usize];
+        let enemy_king = pos.pieces[color.enemy() as usize][Piece::King as usize].msb();
+        let occupied = (pos.occupied & !mv.from().bitboard()) | mv.to().bitboard();
+        let piece = pos.piece_at(mv.from()).unwrap().0;
+
+        let direct_check = match piece {
+            Piece::Pawn => self.pawn_attacks(color, mv.to()),
+            Piece::Knight => self.knight_attacks(mv.to()),
+            Piece::Bishop => self.bishop_moves(mv.to(), occupied),
+            Piece::Rook => self.rook_moves(mv.to(), occupied),
+            Piece::Queen => self.queen_moves(mv.to(), occupied),
+            Piece::King => Bitboard::empty(),
+        }
+        .has(enemy_king);
+
+        // A captured slider can't give check, so it is removed from the attackers.
+        let bishop_queens = (pieces[Piece::Bishop as usize] | pieces[Piece::Queen as usize])
+            & !mv.from().bitboard();
+        let rook_queens =
+            (pieces[Piece::Rook as usize] | pieces[Piece::Queen as usize]) & !mv.from().bitboard();
+        let discovered_check = !(self.bishop_moves(enemy_king, occupied) & bishop_queens
+            | self.rook_moves(enemy_king, occupied) & rook_queens)
+            .is_empty();
+
+        direct_check || discovered_check
+    }
+
+    /// Checks whether `mv` is a move the generators could produce in `pos`, if leaving the own
+    /// king in check was allowed. Castling is checked completely. Meant for moves from the
+    /// transposition table or killer tables, which may come from another position.
+    pub fn is_pseudo_legal(&self, pos: &Position, mv: &Move) -> bool {
+        let color = pos.turn;
+        let (from, to) = (mv.from(), mv.to());
+        let Some((piece, piece_color)) = pos.piece_at(from) else {
+            return false;
+        };
+        if piece_color != color {
+            return false;
+        }
+
+        if mv.is_king_castle() || mv.is_queen_castle() {
+            let king_square = pos.pieces[color as usize][Piece::King as usize].msb();
+            return piece == Piece::King
+                && !self.is_check(pos)
+                && mv.castling_kind(&color).is_some_and(|castling_kind| {
+                    self.generate_castling(pos, &castling_kind, king_square, pos.occupied)
+                        == Some(*mv)
+                });
+        }
 
-                en_passant.offset(offset, 0).unwrap() == attacker_sq
+        if pos.occupation(&color).has(to) {
+            return false;
+        }
+        let capture = pos.occupation(&color.enemy()).has(to);
+
+        let kind = if piece == Piece::Pawn {
+            let promotion = promotion_rank(color).has(to);
+            if self.pawn_attacks(color, from).has(to) {
+                match (capture, promotion) {
+                    (true, true) => MoveKind::PromotionCapture,
+                    (true, false) => MoveKind::Capture,
+                    (false, _) if pos.en_passant == Some(to) => MoveKind::EnPassant,
+                    (false, _) => return false,
+                }
+            } else if capture {
+                return false;
+            } else if self.pawn_single_moves(color, from).has(to) {
+                if promotion {
+                    MoveKind::Promotion
+                } else {
+                    MoveKind::Quiet
+                }
+            } else if self.pawn_double_moves(color, from).has(to)
+                && (self.lookups.in_between[from as usize][to as usize] & pos.occupied).is_empty()
+            {
+                MoveKind::DoublePawnPush
             } else {
-                false
+                return false;
+            }
+        } else {
+            let attacks = match piece {
+                Piece::Knight => self.knight_attacks(from),
+                Piece::Bishop => self.bishop_moves(from, pos.occupied),
+                Piece::Rook => self.rook_moves(from, pos.occupied),
+                Piece::Queen => self.queen_moves(from, pos.occupied),
+                Piece::King => self.king_attacks(from),
+                Piece::Pawn => unreachable!("Pawn moves are checked above"),
             };
+            if !attacks.has(to) {
+                return false;
+            }
+
+            if capture {
+                MoveKind::Capture
+            } else {
+                MoveKind::Quiet
+            }
+        };
+
+        let promotion = match kind {
+            MoveKind::Promotion | MoveKind::PromotionCapture => match mv.promotion() {
+                Some(promotion) => Some(promotion),
+                None => return false,
+            },
+            _ => None,
+        };
+
+        // Comparing the whole encoding also rejects flags which don't match the position.
+        Move::new(from, to, promotion, &kind) == *mv
+    }
 
-            return non_king_moves
-                .chain(king_moves)
-                .filter(move |mv| {
-                    let blockable_squares = if slider.is_some() {
-                        let between =
-                            self.lookups.in_between[attacker_sq as usize][king_square as usize];
-
-                        between & !pos.occupation(&pos.turn)
-                    } else {
-                        Bitboard::empty()
-                    };
-
-                    if matches!(mv.kind(), MoveKind::EnPassant) {
-                        return can_enpass_kill_attacker;
-                    }
-
-                    mv.to() == attacker_sq
-                        || mv.from() == king_square
-                        || !(mv.to().bitboard() & blockable_squares).is_empty()
-                })
-                .collect();
+    /// Checks whether `mv` is legal in `pos`, without generating the other moves.
+    pub fn is_legal(&self, pos: &Position, mv: &Move) -> bool {
+        if !self.is_pseudo_legal(pos, mv) {
+            return false;
         }
-        king_moves.collect()
+
+        // Legality of castling is already checked by `is_pseudo_legal`.
+        if mv.is_king_castle() || mv.is_queen_castle() {
+            return true;
+        }
+
+        let color = pos.turn;
+        let (from, to) = (mv.from(), mv.to());
+        let captured = if mv.is_enpass_capture() {
+            let offset = if color == Color::White { -1 } else { 1 };
+            to.offset(offset, 0).unwrap().bitboard()
+        } else {
+            to.bitboard()
+        };
+
+        let king_square = pos.pieces[color as usize][Piece::King as usize].msb();
+        let king_square = if from == king_square { to } else { king_square };
+        let occupied = (pos.occupied & !from.bitboard() & !captured) | to.bitboard();
+
+        (self.attacks_to_square(pos, king_square, color.enemy(), occupied) & !captured).is_empty()
+    }
+
+    fn generate(&self, pos: &Position, filter: MoveFilter) -> MoveList {
+        let mut moves = MoveList::new();
+        self.generate_into(pos, filter, &mut moves);
+
+        moves
+    }
+
+    /// Appends the legal moves selected by `filter` to `moves`, which must have room for them.
+    pub fn generate_into(&self, pos: &Position, filter: MoveFilter, moves: &mut MoveList) {
+        let friendly_occ = pos.occupation(&pos.turn);
+        let enemy_occ = pos.occupation(&pos.enemy());
+        let pinned_pieces = self.pinned_pieces(pos, pos.turn);
+        let king_square = pos.pieces[pos.turn as usize][Piece::King as usize].msb();
+        let attackers_to_king = self.attacks_to_square(pos, king_square, pos.enemy(), pos.occupied);
+        let mut en_passant = pos.en_passant.map_or(Bitboard::empty(), Square::bitboard);
+
+        // In check, pieces other than the king can only capture the checking piece or block its
+        // attack. In double check only the king can move.
+        let evasion_targets = match attackers_to_king.count() {
+            0 => Bitboard::full(),
+            1 => {
+                let attacker_sq = attackers_to_king.lsb();
+                let offset = if pos.turn == Color::White { -1 } else { 1 };
+                let can_enpass_kill_attacker = pos
+                    .en_passant
+                    .is_some_and(|en_passant| en_passant.offset(offset, 0).unwrap() == attacker_sq);
+                if !can_enpass_kill_attacker {
+                    en_passant = Bitboard::empty();
+                }
+
+                attacker_sq.bitboard()
+                    | self.lookups.in_between[attacker_sq as usize][king_square as usize]
+            }
+            _ => {
+                en_passant = Bitboard::empty();
+                Bitboard::empty()
+            }
+        };
+
+        let targets = match filter {
+            MoveFilter::All => !friendly_occ,
+            MoveFilter::Captures => enemy_occ,
+            MoveFilter::Quiets => !pos.occupied,
+        };
+        let pawn_attack_targets = match filter {
+            MoveFilter::All | MoveFilter::Captures => (enemy_occ & evasion_targets) | en_passant,
+            MoveFilter::Quiets => Bitboard::empty(),
+        };
+        // Pushes to the last rank are the only pawn pushes among captures.
+        let pawn_push_targets = match filter {
+            MoveFilter::All | MoveFilter::Quiets => !pos.occupied & evasion_targets,
+            MoveFilter::Captures => !pos.occupied & evasion_targets & promotion_rank(pos.turn),
+        };
+        let targets_with_check = targets & evasion_targets;
+
+        let pawn_quiet_moves = self
+            .generate_pawn_moves(
+                pos,
+                friendly_occ,
+                enemy_occ,
+                pinned_pieces,
+                king_square,
+                pawn_push_targets,
+            )
+            .filter(move |mv| match filter {
+                MoveFilter::All => true,
+                MoveFilter::Captures => mv.promotion() == Some(Piece::Queen),
+                MoveFilter::Quiets => mv.promotion() != Some(Piece::Queen),
+            });
+        let pawn_capturing_moves = self.generate_pawn_attacks(
+            pos,
+            friendly_occ,
+            enemy_occ,
+            pinned_pieces,
+            king_square,
+            pawn_attack_targets,
+        );
+        let knight_moves = self.generate_knight_moves(
+            pos,
+            friendly_occ,
+            enemy_occ,
+            pinned_pieces,
+            targets_with_check,
+        );
+        let slider_moves = self.generate_slider_moves(
+            pos,
+            friendly_occ,
+            enemy_occ,
+            pinned_pieces,
+            king_square,
+            targets_with_check,
+        );
+        let king_moves =
+            self.generate_king_moves(pos, friendly_occ, enemy_occ, pinned_pieces, targets);
+
+        moves.extend(
+            king_moves
+                .chain(pawn_capturing_moves)
+                .chain(knight_moves)
+                .chain(slider_moves)
+                .chain(pawn_quiet_moves),
+        );
+
+        if filter != MoveFilter::Captures && attackers_to_king.is_empty() {
+            moves.extend(self.generate_all_castlings(pos, friendly_occ, enemy_occ, pinned_pieces));
+        }
+    }
+}
+
+/// Subsets of legal moves produced by `MoveGen::generate_into`. Captures and quiets together are
+/// all legal moves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveFilter {
+    /// All legal moves.
+    All,
+    /// Captures, including capturing promotions, and promotions to a queen.
+    Captures,
+    /// Moves to empty squares, castling and underpromotions without a capture.
+    Quiets,
+}
+
+const fn promotion_rank(color: Color) -> Bitboard {
+    match color {
+        Color::White => Rank::R8.bitboard(),
+        Color::Black => Rank::R1.bitboard(),
     }
 }
 
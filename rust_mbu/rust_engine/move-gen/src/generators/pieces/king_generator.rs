@@ -1,7 +1,7 @@
 use sdk::{
     bitboard::Bitboard,
     position::{CastlingKind, Color, Piece, Position},
-    square::{File, Rank, Square},
+    square::{Rank, Square},
 };
 
 use crate::{
@@ -18,6 +18,7 @@ pub trait KingMoveGenerator {
         friendly_occ: Bitboard,
         enemy_occ: Bitboard,
         pinned_pieces: Bitboard,
+        targets: Bitboard,
     ) -> impl Iterator<Item = Move>;
 
     fn generate_all_castlings<'a>(
@@ -44,12 +45,13 @@ impl KingMoveGenerator for MoveGen {
         friendly_occ: Bitboard,
         _enemy_occ: Bitboard,
         pinned_pieces: Bitboard,
+        targets: Bitboard,
     ) -> impl Iterator<Item = Move> {
         let color = pos.turn;
         let bb = pos.pieces[color as usize][Piece::King as usize] & !pinned_pieces;
 
         bb.into_iter().flat_map(move |from_square| {
-            let attacks = self.king_attacks(from_square) & !friendly_occ;
+            let attacks = self.king_attacks(from_square) & !friendly_occ & targets;
 
             attacks.into_iter().filter_map(move |target_square| {
                 // Move is illegal if it leaves the king in check
@@ -82,25 +84,19 @@ impl KingMoveGenerator for MoveGen {
         let king_square = pos.pieces[pos.turn as usize][Piece::King as usize].msb();
 
         let occ = pos.occupation(&Color::White) | pos.occupation(&Color::Black);
+        let in_check = self.is_check(pos);
 
-        let mut castling_moves = Vec::new();
-
-        if !self.is_check(pos) {
-            for castling_kind in &[
-                CastlingKind::WhiteKingside,
-                CastlingKind::WhiteQueenside,
-                CastlingKind::BlackKingside,
-                CastlingKind::BlackQueenside,
-            ] {
-                if let Some(mv) = self.generate_castling(pos, castling_kind, king_square, occ) {
-                    castling_moves.push(mv);
-                }
-            }
-        }
-
-        castling_moves.into_iter()
+        CastlingKind::all()
+            .into_iter()
+            .filter(move |_| !in_check)
+            .filter_map(move |castling_kind| {
+                self.generate_castling(pos, &castling_kind, king_square, occ)
+            })
     }
 
+    /// Generates castling as king takes rook. The same rules cover standard chess and Chess960:
+    /// every square the king and rook travel over must be empty apart from those two pieces, the
+    /// king may not pass through an attacked square and may not end up in check.
     fn generate_castling<'a>(
         &'a self,
         pos: &'a Position,
@@ -108,67 +104,60 @@ impl KingMoveGenerator for MoveGen {
         king_square: Square,
         occ: Bitboard,
     ) -> Option<Move> {
-        let color = match castling_kind {
-            CastlingKind::WhiteKingside | CastlingKind::WhiteQueenside => Color::White,
-            CastlingKind::BlackKingside | CastlingKind::BlackQueenside => Color::Black,
-        };
+        let color = castling_kind.color();
 
-        if pos.turn != color {
+        if pos.turn != color || !pos.castling.has_castling_kind(castling_kind) {
             return None;
         }
 
-        match castling_kind {
-            CastlingKind::WhiteKingside | CastlingKind::WhiteQueenside => {
-                if king_square != Square::E1 {
-                    return None;
-                }
-            }
-            CastlingKind::BlackKingside | CastlingKind::BlackQueenside => {
-                if king_square != Square::E8 {
-                    return None;
-                }
-            }
+        let back_rank = match color {
+            Color::White => Rank::R1,
+            Color::Black => Rank::R8,
         };
 
-        let between_bb = match castling_kind {
-            CastlingKind::WhiteKingside => Square::F1.bitboard() | Square::G1.bitboard(),
-            CastlingKind::WhiteQueenside => {
-                Square::B1.bitboard() | Square::C1.bitboard() | Square::D1.bitboard()
-            }
-            CastlingKind::BlackKingside => Square::F8.bitboard() | Square::G8.bitboard(),
-            CastlingKind::BlackQueenside => {
-                Square::B8.bitboard() | Square::C8.bitboard() | Square::D8.bitboard()
-            }
-        };
+        if king_square.rank() != back_rank {
+            return None;
+        }
 
-        if !(between_bb & occ).is_empty() {
+        let rook_square = pos.castling.rook_square(castling_kind);
+        if pos.piece_at(rook_square) != Some((Piece::Rook, color)) {
             return None;
         }
 
-        let target_square: Square = match castling_kind {
-            CastlingKind::WhiteKingside | CastlingKind::BlackKingside => {
-                Square::from_u8(king_square as u8 + 2)
-            }
-            CastlingKind::WhiteQueenside | CastlingKind::BlackQueenside => {
-                Square::from_u8(king_square as u8 - 2)
-            }
-        };
+        let (rook_target, king_target) = castling_kind.target_squares();
 
-        if pos.castling.has_castling_kind(castling_kind)
-            && pos.turn == color
-            && (between_bb & !File::B.bitboard()).into_iter().all(|sq| {
-                self.attacks_to_square(pos, sq, pos.enemy(), pos.occupied)
-                    .is_empty()
-            })
+        let in_between = &self.lookups.in_between;
+        let king_path = in_between[king_square as usize][king_target as usize] | king_target;
+        let rook_path = in_between[rook_square as usize][rook_target as usize] | rook_target;
+        let castling_pieces = king_square.bitboard() | rook_square.bitboard();
+
+        if !((king_path | rook_path) & occ & !castling_pieces).is_empty() {
+            return None;
+        }
+
+        let passes_attacked_square = (king_path & !king_target.bitboard())
+            .into_iter()
+            .any(|sq| !self.attacks_to_square(pos, sq, pos.enemy(), occ).is_empty());
+
+        if passes_attacked_square {
+            return None;
+        }
+
+        // Checked with the final occupancy, as the castling rook may have been shielding the
+        // king's target square.
+        let occ_after = (occ & !castling_pieces) | king_target | rook_target;
+        if !self
+            .attacks_to_square(pos, king_target, pos.enemy(), occ_after)
+            .is_empty()
         {
-            return Some(Move::new(
-                king_square,
-                target_square,
-                None,
-                &MoveKind::Castling,
-            ));
+            return None;
         }
 
-        None
+        Some(Move::new(
+            king_square,
+            rook_square,
+            None,
+            &MoveKind::Castling,
+        ))
     }
 }
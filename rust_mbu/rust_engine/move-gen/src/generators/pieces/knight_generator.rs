@@ -17,6 +17,7 @@ pub trait KnightMoveGenerator {
         friendly_occ: Bitboard,
         enemy_occ: Bitboard,
         pinned_pieces: Bitboard,
+        targets: Bitboard,
     ) -> impl Iterator<Item = Move>;
 }
 
@@ -27,12 +28,13 @@ impl KnightMoveGenerator for MoveGen {
         friendly_occ: Bitboard,
         _enemy_occ: Bitboard,
         pinned_pieces: Bitboard,
+        targets: Bitboard,
     ) -> impl Iterator<Item = Move> {
         let color = pos.turn;
         let bb = pos.pieces[color as usize][Piece::Knight as usize] & !pinned_pieces;
 
         bb.into_iter().flat_map(move |from_square| {
-            let attacks = self.knight_attacks(from_square) & !friendly_occ;
+            let attacks = self.knight_attacks(from_square) & !friendly_occ & targets;
 
             attacks.into_iter().map(move |target_square| {
                 let captured_piece = pos.piece_at(target_square).map(|piece| piece.0);
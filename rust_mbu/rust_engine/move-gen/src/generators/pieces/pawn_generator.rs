@@ -20,6 +20,7 @@ pub trait PawnMoveGenerator {
         enemy_occ: Bitboard,
         pinned_pieces: Bitboard,
         king_sq: Square,
+        targets: Bitboard,
     ) -> impl Iterator<Item = Move>;
 
     fn generate_pawn_attacks<'a>(
@@ -29,6 +30,7 @@ pub trait PawnMoveGenerator {
         enemy_occ: Bitboard,
         pinned_pieces: Bitboard,
         king_sq: Square,
+        targets: Bitboard,
     ) -> impl Iterator<Item = Move>;
 }
 
@@ -40,6 +42,7 @@ impl PawnMoveGenerator for MoveGen {
         enemy_occ: Bitboard,
         pinned_pieces: Bitboard,
         king_sq: Square,
+        targets: Bitboard,
     ) -> impl Iterator<Item = Move> {
         let color = pos.turn;
         let bb = pos.pieces[color as usize][Piece::Pawn as usize];
@@ -58,10 +61,11 @@ impl PawnMoveGenerator for MoveGen {
             };
 
             let single_moves =
-                self.pawn_single_moves(color, from_square) & !blockers & maybe_pinner_ray;
+                self.pawn_single_moves(color, from_square) & !blockers & maybe_pinner_ray & targets;
             let double_moves = self.pawn_double_moves(color, from_square)
                 & !double_push_blockers
-                & maybe_pinner_ray;
+                & maybe_pinner_ray
+                & targets;
 
             single_moves
                 .into_iter()
@@ -95,6 +99,7 @@ impl PawnMoveGenerator for MoveGen {
         enemy_occ: Bitboard,
         pinned_pieces: Bitboard,
         king_sq: Square,
+        targets: Bitboard,
     ) -> impl Iterator<Item = Move> {
         let blockers = friendly_occ | enemy_occ;
         let color = pos.turn;
@@ -108,9 +113,12 @@ impl PawnMoveGenerator for MoveGen {
             };
 
             let attacks = if let Some(en_passant) = pos.en_passant {
-                self.pawn_attacks(color, from_square) & (enemy_occ | en_passant) & maybe_pinner_ray
+                self.pawn_attacks(color, from_square)
+                    & (enemy_occ | en_passant)
+                    & maybe_pinner_ray
+                    & targets
             } else {
-                self.pawn_attacks(color, from_square) & enemy_occ & maybe_pinner_ray
+                self.pawn_attacks(color, from_square) & enemy_occ & maybe_pinner_ray & targets
             };
 
             attacks.into_iter().flat_map(move |target_square| {
@@ -20,6 +20,7 @@ pub trait SliderMoveGenerator {
         enemy_occ: Bitboard,
         pinned_pieces: Bitboard,
         king_sq: Square,
+        targets: Bitboard,
     ) -> impl Iterator<Item = Move>;
 }
 
@@ -31,6 +32,7 @@ impl SliderMoveGenerator for MoveGen {
         enemy_occ: Bitboard,
         pinned_pieces: Bitboard,
         king_sq: Square,
+        targets: Bitboard,
     ) -> impl Iterator<Item = Move> {
         [Slider::Bishop, Slider::Rook, Slider::Queen]
             .into_iter()
@@ -49,7 +51,8 @@ impl SliderMoveGenerator for MoveGen {
 
                     let attacks = self.slider_moves(slider, from_square, blockers)
                         & !friendly_occ
-                        & maybe_pinner_ray;
+                        & maybe_pinner_ray
+                        & targets;
 
                     attacks.into_iter().map(move |target_square| {
                         let captured_piece = pos.piece_at(target_square).map(|piece| piece.0);
@@ -5,9 +5,11 @@ use sdk::{
     fen::Fen,
     hash::ZOBRIST_KEYS,
     position::{CastlingKind, Color, Piece, Position},
-    square::{File, Square},
+    square::Square,
 };
 
+pub mod move_list;
+
 type Result<T> = std::result::Result<T, anyhow::Error>;
 
 #[derive(Clone, Copy, Eq)]
@@ -28,9 +30,24 @@ impl PartialEq for Move {
     }
 }
 
+/// State needed to take a move back that can't be recovered from the move itself.
+#[derive(Debug, Clone, Copy)]
+pub struct Undo {
+    pub captured: Option<Piece>,
+    pub castling: u8,
+    pub en_passant: Option<Square>,
+    pub halfmove_clock: u16,
+    pub hash: u64,
+    pub pawn_hash: u64,
+    pub material_key: u64,
+}
+
 pub trait MakeMove {
-    fn make_move(&mut self, mv: &Move) -> Result<Option<Piece>>;
-    fn make_null_move(&mut self);
+    fn make_move(&mut self, mv: &Move) -> Result<Undo>;
+    /// Takes back `mv`, which must be the last move made on this position.
+    fn unmake_move(&mut self, mv: &Move, undo: &Undo);
+    fn make_null_move(&mut self) -> Undo;
+    fn unmake_null_move(&mut self, undo: &Undo);
 }
 
 impl fmt::Debug for Move {
@@ -48,33 +65,31 @@ impl fmt::Debug for Move {
 
 impl fmt::Display for Move {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        let promotion = if let Some(promotion) = self.promotion() {
-            format!("{promotion}")
-        } else {
-            "".to_string()
-        };
-        write!(f, "{}{}{}", self.from(), self.to(), promotion)
+        write!(f, "{}", self.to_uci(false))
     }
 }
 
 impl MakeMove for Position {
-    fn make_move(&mut self, mv: &Move) -> Result<Option<Piece>> {
+    fn make_move(&mut self, mv: &Move) -> Result<Undo> {
         let from = mv.from();
         let to = mv.to();
         let color = self.turn;
 
         let old_castling = self.castling.inner;
+        let mut undo = Undo {
+            captured: None,
+            castling: old_castling,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            hash: self.hash,
+            pawn_hash: self.pawn_hash,
+            material_key: self.material_key,
+        };
 
-        for (rook_sq, kind) in [
-            (Square::A1, CastlingKind::WhiteQueenside),
-            (Square::H1, CastlingKind::WhiteKingside),
-            (Square::A8, CastlingKind::BlackQueenside),
-            (Square::H8, CastlingKind::BlackKingside),
-        ]
-        .iter()
-        {
-            if mv.from() == *rook_sq || mv.to() == *rook_sq {
-                self.castling.remove_castling_kind(kind);
+        for kind in CastlingKind::all() {
+            let rook_sq = self.castling.rook_square(&kind);
+            if from == rook_sq || to == rook_sq {
+                self.castling.remove_castling_kind(&kind);
             }
         }
 
@@ -86,9 +101,21 @@ impl MakeMove for Position {
             self.castling.remove_color_castling(&color);
         }
 
+        // Castling moves are encoded as king takes rook, so the king lands elsewhere.
+        let piece_to = mv
+            .castling_kind(&color)
+            .map_or(to, |castling| castling.target_squares().1);
+
         // Update moved piece hash
         self.hash ^= ZOBRIST_KEYS.pieces[from_color as usize][from_piece as usize][from as usize];
-        self.hash ^= ZOBRIST_KEYS.pieces[from_color as usize][from_piece as usize][to as usize];
+        self.hash ^=
+            ZOBRIST_KEYS.pieces[from_color as usize][from_piece as usize][piece_to as usize];
+        if from_piece == Piece::Pawn {
+            self.pawn_hash ^=
+                ZOBRIST_KEYS.pieces[from_color as usize][Piece::Pawn as usize][from as usize];
+            self.pawn_hash ^=
+                ZOBRIST_KEYS.pieces[from_color as usize][Piece::Pawn as usize][piece_to as usize];
+        }
 
         if matches!(mv.kind(), MoveKind::Castling) {
             self.castling.remove_color_castling(&color);
@@ -119,6 +146,10 @@ impl MakeMove for Position {
                 // Update captured piece hash
                 self.hash ^=
                     ZOBRIST_KEYS.pieces[target_color as usize][target_piece as usize][to as usize];
+                if target_piece == Piece::Pawn {
+                    self.pawn_hash ^= ZOBRIST_KEYS.pieces[target_color as usize]
+                        [Piece::Pawn as usize][to as usize];
+                }
 
                 Some((target_piece, target_color))
             }
@@ -137,6 +168,8 @@ impl MakeMove for Position {
                 // Update captured piece hash
                 self.hash ^= ZOBRIST_KEYS.pieces[target_color as usize][target_piece as usize]
                     [captured_sq as usize];
+                self.pawn_hash ^= ZOBRIST_KEYS.pieces[target_color as usize][Piece::Pawn as usize]
+                    [captured_sq as usize];
 
                 Some((target_piece, target_color))
             }
@@ -145,7 +178,7 @@ impl MakeMove for Position {
                     .castling_kind(&self.turn)
                     .expect("BUG: Move does not castle.");
 
-                let (rook_from, _) = castling.from_squares();
+                let rook_from = to;
                 let (rook_to, king_to) = castling.target_squares();
 
                 let (rook, _) = self.remove_piece_at(rook_from).unwrap_or_else(|| {
@@ -175,10 +208,20 @@ impl MakeMove for Position {
                 // Update pawn hash
                 self.hash ^=
                     ZOBRIST_KEYS.pieces[from_color as usize][Piece::Pawn as usize][to as usize];
+                self.pawn_hash ^=
+                    ZOBRIST_KEYS.pieces[from_color as usize][Piece::Pawn as usize][to as usize];
                 // Update promotion hash
                 self.hash ^=
                     ZOBRIST_KEYS.pieces[from_color as usize][promotion as usize][to as usize];
 
+                // Update material key, the pawn is already removed
+                let pawns = self.pieces[from_color as usize][Piece::Pawn as usize].count();
+                let promoted = self.pieces[from_color as usize][promotion as usize].count();
+                self.material_key ^=
+                    ZOBRIST_KEYS.pieces[from_color as usize][Piece::Pawn as usize][pawns as usize];
+                self.material_key ^= ZOBRIST_KEYS.pieces[from_color as usize][promotion as usize]
+                    [promoted as usize - 1];
+
                 // Update captured piece hash
                 if let Some((target_piece, target_color)) = captured {
                     self.hash ^= ZOBRIST_KEYS.pieces[target_color as usize][target_piece as usize]
@@ -204,8 +247,15 @@ impl MakeMove for Position {
 
                 captured
             }
+        };
+
+        // Update material key with the captured piece
+        if let Some((target_piece, target_color)) = captured {
+            let count = self.pieces[target_color as usize][target_piece as usize].count();
+            self.material_key ^=
+                ZOBRIST_KEYS.pieces[target_color as usize][target_piece as usize][count as usize];
         }
-        .map(|(piece, _)| piece);
+        let captured = captured.map(|(piece, _)| piece);
 
         self.occupied = self.occupation(&Color::White) | self.occupation(&Color::Black);
         if !matches!(mv.kind(), MoveKind::DoublePawnPush) {
@@ -225,16 +275,100 @@ impl MakeMove for Position {
             self.fullmove_number += 1;
         }
 
-        Ok(captured)
+        debug_assert_eq!(self.hash, self.calc_hash());
+        debug_assert_eq!(self.pawn_hash, self.calc_pawn_hash());
+        debug_assert_eq!(self.material_key, self.calc_material_key());
+
+        undo.captured = captured;
+        Ok(undo)
+    }
+
+    fn unmake_move(&mut self, mv: &Move, undo: &Undo) {
+        let from = mv.from();
+        let to = mv.to();
+        let color = self.swap_turn();
+        let enemy = color.enemy();
+
+        if color == Color::Black {
+            self.fullmove_number -= 1;
+        }
+
+        match mv.kind() {
+            MoveKind::Castling => {
+                let castling = mv
+                    .castling_kind(&color)
+                    .expect("BUG: Move does not castle.");
+                let (rook_to, king_to) = castling.target_squares();
+
+                let (king, _) = self.remove_piece_at(king_to).expect("BUG: No king");
+                let (rook, _) = self.remove_piece_at(rook_to).expect("BUG: No rook");
+                self.put_piece(from, king, color);
+                self.put_piece(to, rook, color);
+            }
+            MoveKind::Promotion | MoveKind::PromotionCapture => {
+                self.remove_piece_at(to);
+                self.put_piece(from, Piece::Pawn, color);
+                if let Some(captured) = undo.captured {
+                    self.put_piece(to, captured, enemy);
+                }
+            }
+            MoveKind::EnPassant => {
+                self.remove_piece_at(to);
+                self.put_piece(from, Piece::Pawn, color);
+
+                let captured_sq = to
+                    .offset(if color == Color::White { -1 } else { 1 }, 0)
+                    .expect("BUG: Invalid en passant square");
+                self.put_piece(captured_sq, Piece::Pawn, enemy);
+            }
+            _ => {
+                let (piece, _) = self
+                    .remove_piece_at(to)
+                    .expect("BUG: No piece at to square");
+                self.put_piece(from, piece, color);
+                if let Some(captured) = undo.captured {
+                    self.put_piece(to, captured, enemy);
+                }
+            }
+        }
+
+        self.occupied = self.occupation(&Color::White) | self.occupation(&Color::Black);
+        self.castling.inner = undo.castling;
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.hash = undo.hash;
+        self.pawn_hash = undo.pawn_hash;
+        self.material_key = undo.material_key;
     }
 
-    fn make_null_move(&mut self) {
+    fn make_null_move(&mut self) -> Undo {
+        let undo = Undo {
+            captured: None,
+            castling: self.castling.inner,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            hash: self.hash,
+            pawn_hash: self.pawn_hash,
+            material_key: self.material_key,
+        };
+
         let _ = self.swap_turn();
         self.hash ^= ZOBRIST_KEYS.side_to_move;
         if let Some(en_pass) = self.en_passant {
             self.hash ^= ZOBRIST_KEYS.en_passant[en_pass as usize];
             self.en_passant = None;
         }
+        debug_assert_eq!(self.hash, self.calc_hash());
+
+        undo
+    }
+
+    fn unmake_null_move(&mut self, undo: &Undo) {
+        let _ = self.swap_turn();
+        self.en_passant = undo.en_passant;
+        self.hash = undo.hash;
+        self.pawn_hash = undo.pawn_hash;
+        self.material_key = undo.material_key;
     }
 }
 
@@ -268,10 +402,11 @@ impl Move {
                 mv.set_enpass_capture();
             }
             MoveKind::Castling => {
-                if to.file() == File::C {
-                    mv.set_queen_castle();
-                } else if to.file() == File::G {
+                // Castling is encoded as king takes rook, which also covers Chess960.
+                if to > from {
                     mv.set_king_castle();
+                } else {
+                    mv.set_queen_castle();
                 }
             }
             MoveKind::Promotion => {
@@ -369,6 +504,26 @@ impl Move {
         None
     }
 
+    /// Formats the move in UCI notation. In Chess960 mode castling is written as king takes
+    /// rook, otherwise as the two square king move of standard chess.
+    pub fn to_uci(&self, chess960: bool) -> String {
+        let to = match self.castling_kind(&Color::White) {
+            Some(castling) if !chess960 => {
+                let (_, king_to) = castling.target_squares();
+                Square::from_file_rank(king_to.file(), self.to().rank())
+            }
+            _ => self.to(),
+        };
+
+        let promotion = if let Some(promotion) = self.promotion() {
+            format!("{promotion}")
+        } else {
+            "".to_string()
+        };
+
+        format!("{}{}{}", self.from(), to, promotion)
+    }
+
     pub fn is_irreversible(&self, pos: &Position) -> bool {
         if matches!(self.kind(), MoveKind::Capture | MoveKind::PromotionCapture) {
             return true;
@@ -417,7 +572,11 @@ impl Move {
 
 #[cfg(test)]
 mod tests {
-    use sdk::{fen::Fen, position::Position, square::Square};
+    use sdk::{
+        fen::Fen,
+        position::{Piece, Position},
+        square::Square,
+    };
 
     use super::{MakeMove, Move, MoveKind};
 
@@ -437,4 +596,32 @@ mod tests {
 
         assert_eq!(position.hash, position2.hash);
     }
+
+    #[test]
+    fn test_make_move_pawn_hash_and_material_key() {
+        // exd8=N captures a rook with a promotion, then the pawn recaptures on c5.
+        let mut position =
+            Position::from_fen("3r3k/2P5/8/1p6/2P5/8/8/4K3 w - - 0 1".to_string()).unwrap();
+        let pawn_hash = position.pawn_hash;
+
+        let mv = Move::new(
+            Square::C7,
+            Square::D8,
+            Some(Piece::Knight),
+            &MoveKind::PromotionCapture,
+        );
+        position.make_move(&mv).unwrap();
+        let expected =
+            Position::from_fen("3N3k/8/8/1p6/2P5/8/8/4K3 b - - 0 1".to_string()).unwrap();
+        assert_eq!(position.pawn_hash, expected.pawn_hash);
+        assert_eq!(position.material_key, expected.material_key);
+        assert_ne!(position.pawn_hash, pawn_hash);
+
+        let mv = Move::new(Square::B5, Square::C4, None, &MoveKind::Capture);
+        position.make_move(&mv).unwrap();
+        // Same material with the pieces on other squares.
+        let expected = Position::from_fen("7k/8/8/8/8/1p6/8/N3K3 w - - 0 2".to_string()).unwrap();
+        assert_eq!(position.material_key, expected.material_key);
+        assert_ne!(position.pawn_hash, expected.pawn_hash);
+    }
 }
@@ -1,17 +1,21 @@
 #![allow(dead_code)]
 use std::{collections::HashSet, thread};
 
-use rand::seq::SliceRandom;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
 use sdk::{
     fen::Fen,
-    position::{Color, Position},
+    position::{Color, Piece, Position},
 };
 use serde::Deserialize;
 
 use crate::{
-    generators::movegen::MoveGen,
-    r#move::MakeMove,
-    utils::{chess_notation::ChessNotation, logger::configure_logger},
+    generators::movegen::{MoveFilter, MoveGen},
+    r#move::{move_list::MoveList, MakeMove, Move, MoveKind},
+    utils::{
+        chess_notation::ChessNotation,
+        logger::configure_logger,
+        perft::{perft, PerftOptions},
+    },
 };
 
 #[derive(Deserialize, Debug)]
@@ -72,6 +76,119 @@ fn run_all_tests() {
     }
 
     test_hashes();
+    test_from_san();
+    test_unmake_move();
+    test_move_subsets();
+}
+
+fn test_from_san() {
+    let move_gen = MoveGen::new();
+
+    for file in std::fs::read_dir("src/test_cases").unwrap() {
+        let test = load_test(file.unwrap().file_name().into_string().unwrap());
+        for test_case in test.test_cases {
+            let pos = Position::from_fen(test_case.start.fen).unwrap();
+            for mv in move_gen.generate_legal_moves(&pos) {
+                let san = move_gen.to_algebraic_notation(&pos, &mv);
+                assert_eq!(move_gen.from_san(&pos, &san).unwrap(), mv, "{san}");
+            }
+        }
+    }
+}
+
+fn sorted_uci(moves: impl IntoIterator<Item = Move>) -> Vec<String> {
+    let mut moves: Vec<String> = moves.into_iter().map(|mv| mv.to_uci(true)).collect();
+    moves.sort();
+    moves
+}
+
+fn test_move_subsets() {
+    let move_gen = MoveGen::new();
+
+    for file in std::fs::read_dir("src/test_cases").unwrap() {
+        let test = load_test(file.unwrap().file_name().into_string().unwrap());
+        for test_case in test.test_cases {
+            let pos = Position::from_fen(test_case.start.fen).unwrap();
+            let legal = move_gen.generate_legal_moves(&pos);
+            let captures = move_gen.generate_captures(&pos);
+            let quiets = move_gen.generate_quiets(&pos);
+            let fen = pos.to_fen();
+
+            assert!(
+                captures
+                    .iter()
+                    .all(|mv| mv.is_capture() || mv.promotion() == Some(Piece::Queen)),
+                "{fen}"
+            );
+            assert!(quiets.iter().all(|mv| !mv.is_capture()), "{fen}");
+            assert_eq!(
+                sorted_uci(captures.into_iter().chain(quiets.clone())),
+                sorted_uci(legal.clone()),
+                "{fen}"
+            );
+
+            let mut moves = MoveList::new();
+            move_gen.generate_into(&pos, MoveFilter::Captures, &mut moves);
+            move_gen.generate_into(&pos, MoveFilter::Quiets, &mut moves);
+            assert_eq!(sorted_uci(moves), sorted_uci(legal.clone()), "{fen}");
+
+            let evasions = move_gen.generate_evasions(&pos);
+            if move_gen.is_check(&pos) {
+                assert_eq!(sorted_uci(evasions), sorted_uci(legal.clone()), "{fen}");
+            } else {
+                assert!(evasions.is_empty(), "{fen}");
+            }
+
+            let checks: Vec<Move> = legal
+                .into_iter()
+                .filter(|mv| {
+                    let mut child = pos.clone();
+                    child.make_move(mv).unwrap();
+                    move_gen.is_check(&child)
+                })
+                .collect();
+            for mv in &checks {
+                assert!(move_gen.gives_check(&pos, mv), "{fen} move: {mv}");
+            }
+            assert_eq!(
+                sorted_uci(move_gen.generate_quiet_checks(&pos)),
+                sorted_uci(quiets.into_iter().filter(|mv| checks.contains(mv))),
+                "{fen}"
+            );
+        }
+    }
+}
+
+fn assert_same_position(actual: &Position, expected: &Position, context: &str) {
+    assert_eq!(actual.to_fen(), expected.to_fen(), "{context}");
+    assert_eq!(actual.hash, expected.hash, "{context}");
+    assert_eq!(actual.pawn_hash, expected.pawn_hash, "{context}");
+    assert_eq!(actual.material_key, expected.material_key, "{context}");
+    assert_eq!(actual.pieces, expected.pieces, "{context}");
+    assert_eq!(actual.occupied, expected.occupied, "{context}");
+    assert_eq!(actual.mailbox, expected.mailbox, "{context}");
+}
+
+fn test_unmake_move() {
+    let move_gen = MoveGen::new();
+
+    for file in std::fs::read_dir("src/test_cases").unwrap() {
+        let test = load_test(file.unwrap().file_name().into_string().unwrap());
+        for test_case in test.test_cases {
+            let original = Position::from_fen(test_case.start.fen).unwrap();
+            let mut pos = original.clone();
+
+            for mv in move_gen.generate_legal_moves(&original) {
+                let undo = pos.make_move(&mv).unwrap();
+                pos.unmake_move(&mv, &undo);
+                assert_same_position(&pos, &original, &format!("{original} move: {mv}"));
+            }
+
+            let undo = pos.make_null_move();
+            pos.unmake_null_move(&undo);
+            assert_same_position(&pos, &original, &format!("{original} null move"));
+        }
+    }
 }
 
 fn test_hashes() {
@@ -139,3 +256,188 @@ fn run_test(json_name: String) {
         info!("[{} ({})] passed.", json_name, idx + 1);
     }
 }
+
+/// Chess960 positions with their perft node counts for depths 1 to 3.
+const CHESS960_PERFT: [(&str, [usize; 3]); 6] = [
+    (
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        [21, 528, 12189],
+    ),
+    (
+        "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+        [21, 807, 18002],
+    ),
+    (
+        "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+        [20, 479, 10471],
+    ),
+    (
+        "qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9",
+        [22, 593, 13440],
+    ),
+    (
+        "qnbnr1kr/ppp1b1pp/4p3/3p1p2/8/2NPP3/PPP1BPPP/QNB1R1KR w HEhe - 1 9",
+        [29, 899, 26578],
+    ),
+    (
+        "q1bnrkr1/ppppp2p/2n2p2/4b1p1/2NP4/8/PPP1PPPP/QNB1RRKB w ge - 1 9",
+        [30, 860, 24566],
+    ),
+];
+
+#[test]
+fn test_chess960_perft() {
+    let move_gen = MoveGen::new();
+
+    for (fen, expected) in CHESS960_PERFT {
+        let pos = Position::from_fen(fen.to_string()).unwrap();
+
+        for (depth, nodes) in expected.into_iter().enumerate() {
+            let options = PerftOptions::default();
+            assert_eq!(
+                perft(&move_gen, &pos, depth + 1, &options),
+                nodes,
+                "Fen: {fen}"
+            );
+        }
+        assert_eq!(pos.to_shredder_fen(), fen);
+    }
+}
+
+#[test]
+fn test_chess960_castling_uci() {
+    let move_gen = MoveGen::new();
+    let pos = Position::from_fen(
+        "qnbnr1kr/ppp1b1pp/4p3/3p1p2/8/2NPP3/PPP1BPPP/QNB1R1KR w HEhe - 0 1".to_string(),
+    )
+    .unwrap();
+
+    let castling = move_gen
+        .generate_legal_moves(&pos)
+        .into_iter()
+        .find(|mv| matches!(mv.kind(), MoveKind::Castling))
+        .unwrap();
+
+    assert_eq!(castling.to_uci(true), "g1h1");
+    assert_eq!(castling.to_uci(false), "g1g1");
+    assert_eq!(move_gen.to_algebraic_notation(&pos, &castling), "O-O");
+
+    let mut child = pos.clone();
+    child.make_move(&castling).unwrap();
+    assert_eq!(
+        child.to_fen(),
+        "qnbnr1kr/ppp1b1pp/4p3/3p1p2/8/2NPP3/PPP1BPPP/QNB1RRK1 b kq - 1 1"
+    );
+    assert_eq!(child.hash, child.calc_hash());
+}
+
+#[test]
+fn test_tolerant_san() {
+    let move_gen = MoveGen::new();
+    let pos =
+        Position::from_fen("r3k2r/1P1n1ppp/8/8/8/8/6PP/R3K1NR w KQkq - 0 1".to_string()).unwrap();
+
+    let san = |san: &str| {
+        let mv = move_gen.from_san(&pos, san).unwrap();
+        move_gen.to_algebraic_notation(&pos, &mv)
+    };
+
+    assert_eq!(san("0-0-0"), "O-O-O");
+    assert_eq!(san("O-O-O+"), "O-O-O");
+    assert_eq!(san("nf3"), "Nf3");
+    assert_eq!(san("Ng1-f3"), "Nf3");
+    assert_eq!(san("b8Q"), "b8=Q+");
+    assert_eq!(san("bxa8=N"), "bxa8=N");
+    assert_eq!(san("bxa8q"), "bxa8=Q+");
+    assert_eq!(san("h4!?"), "h4");
+    assert!(move_gen.from_san(&pos, "Nd2").is_err());
+    assert!(move_gen.from_san(&pos, "Ke3").is_err());
+    assert!(move_gen.from_san(&pos, "Qd1").is_err());
+}
+
+#[test]
+fn test_san_disambiguation_and_mate() {
+    let move_gen = MoveGen::new();
+    // The knight on d2 is pinned, so Nf3 does not need a file.
+    let pos = Position::from_fen("4k3/8/8/8/1q6/8/3N4/4K1N1 w - - 0 1".to_string()).unwrap();
+    let mv = move_gen.from_san(&pos, "Nf3").unwrap();
+    assert_eq!(move_gen.to_algebraic_notation(&pos, &mv), "Nf3");
+
+    let pos = Position::from_fen("6k1/8/8/8/Q2Q4/8/8/Q3K3 w - - 0 1".to_string()).unwrap();
+    let mv = move_gen.from_san(&pos, "Qa4d1").unwrap();
+    assert_eq!(move_gen.to_algebraic_notation(&pos, &mv), "Qa4d1");
+    assert!(move_gen.from_san(&pos, "Qd1").is_err());
+
+    let pos = Position::from_fen("6k1/5ppp/8/8/8/8/8/R3K3 w - - 0 1".to_string()).unwrap();
+    let mv = move_gen.from_san(&pos, "Ra8").unwrap();
+    assert_eq!(move_gen.to_algebraic_notation(&pos, &mv), "Ra8#");
+}
+
+#[test]
+fn test_move_validation_fuzz() {
+    let move_gen = MoveGen::new();
+    let mut rng = StdRng::seed_from_u64(0x5EED);
+    let mut positions: Vec<Position> = std::fs::read_dir("src/test_cases")
+        .unwrap()
+        .flat_map(|file| load_test(file.unwrap().file_name().into_string().unwrap()).test_cases)
+        .map(|test_case| Position::from_fen(test_case.start.fen).unwrap())
+        .collect();
+    positions.extend(CHESS960_PERFT.map(|(fen, _)| Position::from_fen(fen.to_string()).unwrap()));
+
+    // Moves legal in other positions are the likely result of a hash collision.
+    let mut candidates: Vec<Move> = Vec::new();
+
+    for start in positions {
+        let mut pos = start;
+        for _ in 0..20 {
+            let legal = move_gen.generate_legal_moves(&pos);
+            if legal.is_empty() {
+                break;
+            }
+            candidates.extend(legal.iter().copied());
+
+            let random_moves: Vec<Move> = (0..100)
+                .map(|_| Move {
+                    inner: rng.gen::<u16>(),
+                })
+                .collect();
+            let collisions: Vec<Move> = (0..100)
+                .map(|_| *candidates.choose(&mut rng).unwrap())
+                .collect();
+            // Legal moves with other flags or squares.
+            let mutations: Vec<Move> = legal
+                .iter()
+                .map(|mv| Move {
+                    inner: mv.inner ^ (1 << rng.gen_range(0..16)),
+                })
+                .collect();
+
+            for mv in legal
+                .iter()
+                .copied()
+                .chain(random_moves)
+                .chain(collisions)
+                .chain(mutations)
+            {
+                let fen = pos.to_fen();
+                let is_legal = legal.contains(&mv);
+                assert_eq!(move_gen.is_legal(&pos, &mv), is_legal, "{fen} move: {mv}");
+
+                if is_legal {
+                    assert!(move_gen.is_pseudo_legal(&pos, &mv), "{fen} move: {mv}");
+                }
+
+                // Pseudo-legal moves not in the legal list must leave the king in check.
+                let mut child = pos.clone();
+                if move_gen.is_pseudo_legal(&pos, &mv) && !is_legal && child.make_move(&mv).is_ok()
+                {
+                    child.turn = pos.turn;
+                    assert!(move_gen.is_check(&child), "{fen} move: {mv}");
+                }
+            }
+
+            let mv = *legal.choose(&mut rng).unwrap();
+            pos.make_move(&mv).unwrap();
+        }
+    }
+}
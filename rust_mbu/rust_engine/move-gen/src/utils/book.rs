@@ -0,0 +1,285 @@
+use std::{fs, io, path::Path};
+
+use rand::Rng;
+use sdk::position::Position;
+
+use crate::{generators::movegen::MoveGen, r#move::Move};
+
+/// Size of a Polyglot book entry in bytes.
+pub const ENTRY_SIZE: usize = 16;
+
+/// A Polyglot book entry. On disk all fields are big-endian and entries are sorted by key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookEntry {
+    /// [`Position::polyglot_key`] of the position the move is played from.
+    pub key: u64,
+    pub mv: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookSelection {
+    /// Always play the move with the highest weight.
+    Best,
+    /// Play a random move with probability proportional to its weight.
+    Weighted,
+}
+
+/// A Polyglot opening book, e.g. `book.bin`.
+#[derive(Debug, Clone, Default)]
+pub struct Book {
+    entries: Vec<BookEntry>,
+}
+
+impl BookEntry {
+    #[must_use]
+    pub fn from_bytes(bytes: &[u8; ENTRY_SIZE]) -> BookEntry {
+        let (key, rest) = bytes.split_at(8);
+        let (mv, rest) = rest.split_at(2);
+        let (weight, learn) = rest.split_at(2);
+
+        BookEntry {
+            key: u64::from_be_bytes(key.try_into().unwrap()),
+            mv: u16::from_be_bytes(mv.try_into().unwrap()),
+            weight: u16::from_be_bytes(weight.try_into().unwrap()),
+            learn: u32::from_be_bytes(learn.try_into().unwrap()),
+        }
+    }
+
+    #[must_use]
+    pub fn to_bytes(&self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.mv.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..].copy_from_slice(&self.learn.to_be_bytes());
+
+        bytes
+    }
+
+    /// Polyglot move encoding: to square in bits 0-5, from square in bits 6-11 and the promotion
+    /// piece in bits 12-14. Castling is encoded as the king taking its rook, same as in [`Move`].
+    #[must_use]
+    pub fn encode_move(mv: &Move) -> u16 {
+        let promotion = mv.promotion().map_or(0, |piece| piece as u16);
+
+        mv.to() as u16 | (mv.from() as u16) << 6 | promotion << 12
+    }
+
+    /// Finds the legal move of `pos` that this entry encodes.
+    #[must_use]
+    pub fn decode_move(&self, movegen: &MoveGen, pos: &Position) -> Option<Move> {
+        movegen
+            .generate_legal_moves(pos)
+            .into_iter()
+            .find(|mv| BookEntry::encode_move(mv) == self.mv)
+    }
+}
+
+impl Book {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Book> {
+        Book::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Book> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Book size is not a multiple of {ENTRY_SIZE} bytes"),
+            ));
+        }
+
+        let entries = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|chunk| BookEntry::from_bytes(chunk.try_into().unwrap()))
+            .collect();
+
+        Ok(Book::from_entries(entries))
+    }
+
+    /// Creates a book from entries in any order.
+    #[must_use]
+    pub fn from_entries(mut entries: Vec<BookEntry>) -> Book {
+        if !entries.is_sorted_by_key(|entry| entry.key) {
+            entries.sort_by_key(|entry| entry.key);
+        }
+
+        Book { entries }
+    }
+
+    #[must_use]
+    pub fn entries(&self) -> &[BookEntry] {
+        &self.entries
+    }
+
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.entries.iter().flat_map(BookEntry::to_bytes).collect()
+    }
+
+    /// Entries of the position with the given Polyglot key.
+    #[must_use]
+    pub fn probe(&self, key: u64) -> &[BookEntry] {
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let len = self.entries[start..].partition_point(|entry| entry.key == key);
+
+        &self.entries[start..start + len]
+    }
+
+    /// Combines two books. Positions found in both keep the moves of `self`, since weights of
+    /// different books aren't comparable.
+    #[must_use]
+    pub fn merge(&self, other: &Book) -> Book {
+        let mut entries = self.entries.clone();
+        entries.extend(
+            other
+                .entries
+                .iter()
+                .filter(|entry| self.probe(entry.key).is_empty()),
+        );
+
+        Book::from_entries(entries)
+    }
+
+    /// Legal book moves of the position with their weights. Entries that don't decode to a legal
+    /// move, e.g. from a key collision, are skipped.
+    #[must_use]
+    pub fn moves(&self, movegen: &MoveGen, pos: &Position) -> Vec<(Move, u16)> {
+        self.probe(pos.polyglot_key())
+            .iter()
+            .filter_map(|entry| Some((entry.decode_move(movegen, pos)?, entry.weight)))
+            .collect()
+    }
+
+    /// Picks a book move for the position. Moves with zero weight are never played.
+    #[must_use]
+    pub fn choose(
+        &self,
+        movegen: &MoveGen,
+        pos: &Position,
+        selection: BookSelection,
+    ) -> Option<Move> {
+        let moves = self.moves(movegen, pos);
+
+        match selection {
+            BookSelection::Best => moves
+                .into_iter()
+                .filter(|(_, weight)| *weight > 0)
+                .max_by_key(|(_, weight)| *weight)
+                .map(|(mv, _)| mv),
+            BookSelection::Weighted => {
+                let total = moves
+                    .iter()
+                    .map(|(_, weight)| u32::from(*weight))
+                    .sum::<u32>();
+                if total == 0 {
+                    return None;
+                }
+
+                let mut pick = rand::thread_rng().gen_range(0..total);
+                moves.into_iter().find_map(|(mv, weight)| {
+                    if pick < u32::from(weight) {
+                        Some(mv)
+                    } else {
+                        pick -= u32::from(weight);
+                        None
+                    }
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sdk::{fen::Fen, position::Position};
+
+    use crate::{
+        generators::movegen::MoveGen,
+        r#move::MakeMove,
+        utils::{
+            book::{Book, BookEntry, BookSelection},
+            chess_notation::ChessNotation,
+        },
+    };
+
+    fn entry(movegen: &MoveGen, pos: &Position, san: &str, weight: u16) -> BookEntry {
+        BookEntry {
+            key: pos.polyglot_key(),
+            mv: BookEntry::encode_move(&movegen.from_san(pos, san).unwrap()),
+            weight,
+            learn: 0,
+        }
+    }
+
+    #[test]
+    fn test_book_probe() {
+        let movegen = MoveGen::new();
+        let start = Position::default();
+        let mut after_e4 = start.clone();
+        after_e4
+            .make_move(&movegen.from_san(&start, "e4").unwrap())
+            .unwrap();
+
+        let book = Book::from_entries(vec![
+            entry(&movegen, &after_e4, "c5", 10),
+            entry(&movegen, &start, "d4", 5),
+            entry(&movegen, &start, "e4", 20),
+            entry(&movegen, &start, "a3", 0),
+        ]);
+        let book = Book::from_bytes(&book.to_bytes()).unwrap();
+
+        assert_eq!(book.probe(start.polyglot_key()).len(), 3);
+        assert_eq!(book.moves(&movegen, &after_e4).len(), 1);
+
+        let e4 = movegen.from_san(&start, "e4").unwrap();
+        let a3 = movegen.from_san(&start, "a3").unwrap();
+        assert_eq!(book.choose(&movegen, &start, BookSelection::Best), Some(e4));
+        for _ in 0..20 {
+            let mv = book.choose(&movegen, &start, BookSelection::Weighted);
+            assert!(mv.is_some_and(|mv| mv != a3));
+        }
+
+        let merged = Book::from_entries(vec![
+            entry(&movegen, &start, "c4", 7),
+            entry(&movegen, &after_e4, "e5", 3),
+        ])
+        .merge(&book);
+        assert_eq!(merged.moves(&movegen, &start).len(), 1);
+        assert_eq!(merged.moves(&movegen, &after_e4).len(), 1);
+        assert_eq!(merged.entries().len(), 2);
+
+        let out_of_book = Position::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1".to_string()).unwrap();
+        assert_eq!(
+            book.choose(&movegen, &out_of_book, BookSelection::Best),
+            None
+        );
+        assert!(Book::from_bytes(&[0; 17]).is_err());
+    }
+
+    #[test]
+    fn test_book_castling_and_promotion_encoding() {
+        let movegen = MoveGen::new();
+        let pos = Position::from_fen("4k3/1P6/8/8/8/8/8/4K2R w K - 0 1".to_string()).unwrap();
+
+        // e1h1, as in the Polyglot specification.
+        let castling = movegen.from_san(&pos, "O-O").unwrap();
+        assert_eq!(BookEntry::encode_move(&castling), 0x0107);
+
+        let promotion = movegen.from_san(&pos, "b8=Q+").unwrap();
+        let encoded = BookEntry::encode_move(&promotion);
+        assert_eq!(encoded >> 12, 4);
+
+        let book = Book::from_entries(vec![BookEntry {
+            key: pos.polyglot_key(),
+            mv: encoded,
+            weight: 1,
+            learn: 0,
+        }]);
+        assert_eq!(
+            book.choose(&movegen, &pos, BookSelection::Best),
+            Some(promotion)
+        );
+    }
+}
@@ -1,7 +1,8 @@
+use anyhow::{anyhow, Result};
 use itertools::Itertools;
 use sdk::{
     position::{Piece, Position},
-    square::Square,
+    square::{File, Rank, Square},
 };
 
 use crate::{
@@ -12,6 +13,11 @@ use crate::{
 pub trait ChessNotation {
     fn get_from_notation(&self, pos: &Position, mv: &Move) -> (String, String);
     fn to_algebraic_notation(&self, pos: &Position, mv: &Move) -> String;
+    /// Resolves a SAN move against the legal moves of the position. Accepts the tolerant forms
+    /// found in real PGNs: `0-0`, missing or extra check markers, lowercase piece letters,
+    /// promotions without `=` (`e8Q`) and long algebraic moves (`Ng1-f3`).
+    #[allow(clippy::wrong_self_convention)]
+    fn from_san(&self, pos: &Position, san: &str) -> Result<Move>;
 }
 
 impl ChessNotation for MoveGen {
@@ -20,93 +26,220 @@ impl ChessNotation for MoveGen {
         let from_piece = pos.piece_at(from_square);
         let is_pawn = matches!(from_piece.map(|p| p.0), Some(Piece::Pawn));
 
-        let squares = self
-            .attacks_to_square(pos, mv.to(), pos.turn, pos.occupied)
-            .into_iter()
-            .filter(|sq| sq != &from_square && pos.piece_at(*sq) == from_piece)
-            .collect_vec();
-
-        let mut can_distinguish_by_file = true;
-        let mut can_distinguish_by_rank = true;
-
-        for sq in &squares {
-            if sq.file() == from_square.file() {
-                can_distinguish_by_file = false;
-            }
-            if sq.rank() == from_square.rank() {
-                can_distinguish_by_rank = false;
-            }
-        }
-
         let file = from_square.file().to_string();
         let rank = from_square.rank().to_string();
-        let empty = "".to_string();
+        let empty = String::new();
 
-        if squares.is_empty() && is_pawn {
-            return (file, empty);
-        } else if squares.is_empty() {
-            return (empty.clone(), empty);
+        if is_pawn {
+            return if mv.is_capture() {
+                (file, empty)
+            } else {
+                (empty.clone(), empty)
+            };
         }
 
-        if can_distinguish_by_file {
+        let squares = self
+            .generate_legal_moves(pos)
+            .into_iter()
+            .filter(|other| {
+                other.to() == mv.to()
+                    && other.from() != from_square
+                    && !matches!(other.kind(), MoveKind::Castling)
+                    && pos.piece_at(other.from()) == from_piece
+            })
+            .map(|other| other.from())
+            .collect_vec();
+
+        if squares.is_empty() {
+            (empty.clone(), empty)
+        } else if squares.iter().all(|sq| sq.file() != from_square.file()) {
             (file, empty)
-        } else if can_distinguish_by_rank {
+        } else if squares.iter().all(|sq| sq.rank() != from_square.rank()) {
             (empty, rank)
         } else {
-            (empty.clone(), empty)
+            (file, rank)
         }
     }
 
     fn to_algebraic_notation(&self, pos: &Position, mv: &Move) -> String {
         let (piece, _) = pos.piece_at(mv.from()).expect("No piece at from square.");
 
-        if piece == Piece::King
-            && matches!(mv.kind(), MoveKind::Castling)
-            && ((mv.from() == Square::E1 && mv.to() == Square::G1)
-                || (mv.from() == Square::E8 && mv.to() == Square::G8))
-        {
-            return "O-O".to_string();
-        }
-        if piece == Piece::King
-            && matches!(mv.kind(), MoveKind::Castling)
-            && ((mv.from() == Square::E1 && mv.to() == Square::C1)
-                || (mv.from() == Square::E8 && mv.to() == Square::C8))
-        {
-            return "O-O-O".to_string();
+        let san = if matches!(mv.kind(), MoveKind::Castling) {
+            if mv.is_king_castle() {
+                "O-O".to_string()
+            } else {
+                "O-O-O".to_string()
+            }
+        } else {
+            let piece_char = if piece == Piece::Pawn {
+                String::new()
+            } else {
+                piece.to_string().to_uppercase()
+            };
+            let (from_file, from_rank) = self.get_from_notation(pos, mv);
+            let capture_indicator = if mv.is_capture() { "x" } else { "" };
+            let promoted_to = mv
+                .promotion()
+                .map(|piece| format!("={}", piece.to_string().to_uppercase()))
+                .unwrap_or_default();
+
+            format!(
+                "{piece_char}{from_file}{from_rank}{capture_indicator}{}{promoted_to}",
+                mv.to()
+            )
+        };
+
+        let mut cloned_pos = pos.clone();
+        cloned_pos.make_move(mv).unwrap();
+        let check_indicator = if !self.is_check(&cloned_pos) {
+            ""
+        } else if self.generate_legal_moves(&cloned_pos).is_empty() {
+            "#"
+        } else {
+            "+"
+        };
+
+        format!("{san}{check_indicator}")
+    }
+
+    fn from_san(&self, pos: &Position, san: &str) -> Result<Move> {
+        let stripped = san.trim().trim_end_matches(['+', '#', '!', '?']);
+        let legal_moves = self.generate_legal_moves(pos);
+
+        let castling = match stripped.to_ascii_uppercase().replace('0', "O").as_str() {
+            "O-O" => Some(true),
+            "O-O-O" => Some(false),
+            _ => None,
+        };
+        if let Some(kingside) = castling {
+            return legal_moves
+                .into_iter()
+                .find(|mv| {
+                    matches!(mv.kind(), MoveKind::Castling) && mv.is_king_castle() == kingside
+                })
+                .ok_or(anyhow!("Illegal move: {san}"));
         }
 
-        let piece_char = piece.to_string().to_uppercase();
+        let pattern = SanPattern::parse(stripped).ok_or(anyhow!("Invalid SAN: {san}"))?;
+        // A lowercase `b` is a pawn on the b-file in proper SAN, but may also be a bishop.
+        let patterns = if pattern.piece == Piece::Pawn && stripped.starts_with('b') {
+            vec![
+                Some(pattern),
+                SanPattern::parse(&stripped.replacen('b', "B", 1)),
+            ]
+        } else {
+            vec![Some(pattern)]
+        };
 
-        let is_pawn = piece == Piece::Pawn;
+        for pattern in patterns.into_iter().flatten() {
+            let matching = legal_moves
+                .iter()
+                .filter(|mv| pattern.matches(pos, mv))
+                .collect_vec();
 
-        let (from_file, from_rank) = self.get_from_notation(pos, mv);
+            match matching[..] {
+                [] => continue,
+                [mv] => return Ok(*mv),
+                _ => return Err(anyhow!("Ambiguous move: {san}")),
+            }
+        }
 
-        let to_square = mv.to().to_string();
-        let promoted_to = mv
-            .promotion()
-            .map(|piece| format!("={}", piece.to_string().to_uppercase()))
-            .unwrap_or("".to_string());
+        Err(anyhow!("Illegal move: {san}"))
+    }
+}
 
-        let capture_indicator = if mv.is_capture() { "x" } else { "" };
+/// The parts of a SAN move needed to find it among the legal moves.
+struct SanPattern {
+    piece: Piece,
+    from_file: Option<File>,
+    from_rank: Option<Rank>,
+    to: Square,
+    promotion: Option<Piece>,
+}
 
-        let check_indicator = {
-            let mut cloned_pos = pos.clone();
-            cloned_pos.make_move(mv).unwrap();
-            if self.is_check(&cloned_pos) {
-                "+"
-            } else {
-                ""
+impl SanPattern {
+    fn parse(san: &str) -> Option<SanPattern> {
+        let mut chars = san
+            .chars()
+            .filter(|c| !matches!(c, 'x' | ':' | '-' | '='))
+            .collect_vec();
+
+        let promotion = match chars.last()? {
+            c if chars.len() > 2 && chars[chars.len() - 2].is_ascii_digit() => {
+                let piece = match c.to_ascii_lowercase() {
+                    'n' => Piece::Knight,
+                    'b' => Piece::Bishop,
+                    'r' => Piece::Rook,
+                    'q' => Piece::Queen,
+                    _ => return None,
+                };
+                chars.pop();
+                Some(piece)
             }
+            _ => None,
         };
 
-        if is_pawn && mv.is_capture() {
-            format!("{from_file}{from_rank}x{to_square}{promoted_to}{check_indicator}")
-        } else if is_pawn {
-            format!("{to_square}{promoted_to}{check_indicator}")
-        } else {
-            format!(
-                "{piece_char}{from_file}{from_rank}{capture_indicator}{to_square}{check_indicator}"
-            )
+        if chars.len() < 2 {
+            return None;
+        }
+        let to = parse_square(chars[chars.len() - 2], chars[chars.len() - 1])?;
+        chars.truncate(chars.len() - 2);
+
+        let piece = match chars.first() {
+            Some(c @ ('N' | 'B' | 'R' | 'Q' | 'K' | 'n' | 'r' | 'q' | 'k')) => {
+                let piece = match c.to_ascii_lowercase() {
+                    'n' => Piece::Knight,
+                    'b' => Piece::Bishop,
+                    'r' => Piece::Rook,
+                    'q' => Piece::Queen,
+                    _ => Piece::King,
+                };
+                chars.remove(0);
+                piece
+            }
+            Some('P') => {
+                chars.remove(0);
+                Piece::Pawn
+            }
+            _ => Piece::Pawn,
+        };
+
+        let mut from_file = None;
+        let mut from_rank = None;
+        for c in chars {
+            match c {
+                'a'..='h' => from_file = Some(File::from_u8(c as u8 - b'a')),
+                '1'..='8' => from_rank = Some(Rank::from_u8(c as u8 - b'1')),
+                _ => return None,
+            }
         }
+
+        Some(SanPattern {
+            piece,
+            from_file,
+            from_rank,
+            to,
+            promotion,
+        })
+    }
+
+    fn matches(&self, pos: &Position, mv: &Move) -> bool {
+        pos.piece_at(mv.from()).map(|(piece, _)| piece) == Some(self.piece)
+            && !matches!(mv.kind(), MoveKind::Castling)
+            && mv.to() == self.to
+            && mv.promotion() == self.promotion
+            && self.from_file.is_none_or(|file| mv.from().file() == file)
+            && self.from_rank.is_none_or(|rank| mv.from().rank() == rank)
     }
 }
+
+fn parse_square(file: char, rank: char) -> Option<Square> {
+    if !matches!(file, 'a'..='h') || !matches!(rank, '1'..='8') {
+        return None;
+    }
+
+    Some(Square::from_file_rank(
+        File::from_u8(file as u8 - b'a'),
+        Rank::from_u8(rank as u8 - b'1'),
+    ))
+}
@@ -0,0 +1,401 @@
+use std::{fmt::Display, str::FromStr};
+
+use itertools::Itertools;
+use sdk::{
+    fen::{Fen, FenError},
+    position::Position,
+};
+
+use crate::{
+    generators::movegen::MoveGen,
+    r#move::{MakeMove, Move},
+    utils::chess_notation::ChessNotation,
+};
+
+/// A position from an EPD record together with its operations, e.g.
+/// `2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001";`.
+#[derive(Debug, Clone)]
+pub struct Epd {
+    pub position: Position,
+    pub operations: Vec<Operation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    /// `bm`: best moves.
+    BestMoves(Vec<Move>),
+    /// `am`: moves to avoid.
+    AvoidMoves(Vec<Move>),
+    /// `id`: position identifier.
+    Id(String),
+    /// `c0`: comment.
+    Comment(String),
+    /// `ce`: centipawn evaluation from the side to move's point of view.
+    CentipawnEval(i32),
+    /// `dm`: direct mate in the given number of moves.
+    DirectMate(u32),
+    /// `acd`: analysis depth in plies.
+    AnalysisDepth(u32),
+    /// `pv`: predicted variation, each move played from the previous one.
+    PredictedVariation(Vec<Move>),
+    /// Any other opcode, kept verbatim so it can be written back.
+    Other(String, String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EpdError {
+    Fen(FenError),
+    UnterminatedString(String),
+    MissingOperand(String),
+    InvalidOperand(String, String),
+    IllegalMove(String),
+}
+
+impl Display for EpdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EpdError::Fen(e) => write!(f, "{e}"),
+            EpdError::UnterminatedString(opcode) => {
+                write!(f, "Invalid EPD: unterminated string in {opcode}")
+            }
+            EpdError::MissingOperand(opcode) => write!(f, "Invalid EPD: {opcode} has no operand"),
+            EpdError::InvalidOperand(opcode, operand) => {
+                write!(f, "Invalid EPD: invalid operand {operand} for {opcode}")
+            }
+            EpdError::IllegalMove(san) => write!(f, "Invalid EPD: illegal move {san}"),
+        }
+    }
+}
+
+impl std::error::Error for EpdError {}
+
+impl From<FenError> for EpdError {
+    fn from(value: FenError) -> Self {
+        EpdError::Fen(value)
+    }
+}
+
+impl Operation {
+    #[must_use]
+    pub fn opcode(&self) -> &str {
+        match self {
+            Operation::BestMoves(_) => "bm",
+            Operation::AvoidMoves(_) => "am",
+            Operation::Id(_) => "id",
+            Operation::Comment(_) => "c0",
+            Operation::CentipawnEval(_) => "ce",
+            Operation::DirectMate(_) => "dm",
+            Operation::AnalysisDepth(_) => "acd",
+            Operation::PredictedVariation(_) => "pv",
+            Operation::Other(opcode, _) => opcode,
+        }
+    }
+
+    fn parse(
+        movegen: &MoveGen,
+        pos: &Position,
+        opcode: &str,
+        operands: &[String],
+        raw: &str,
+    ) -> Result<Operation, EpdError> {
+        let operation = match opcode {
+            "bm" => Operation::BestMoves(resolve_moves(movegen, pos, operands, false)?),
+            "am" => Operation::AvoidMoves(resolve_moves(movegen, pos, operands, false)?),
+            "pv" => Operation::PredictedVariation(resolve_moves(movegen, pos, operands, true)?),
+            "id" => Operation::Id(single_operand(opcode, operands)?),
+            "c0" => Operation::Comment(single_operand(opcode, operands)?),
+            "ce" => Operation::CentipawnEval(number_operand(opcode, operands)?),
+            "dm" => Operation::DirectMate(number_operand(opcode, operands)?),
+            "acd" => Operation::AnalysisDepth(number_operand(opcode, operands)?),
+            _ => Operation::Other(opcode.to_string(), raw.to_string()),
+        };
+
+        Ok(operation)
+    }
+
+    fn write(&self, movegen: &MoveGen, pos: &Position) -> String {
+        let operands = match self {
+            Operation::BestMoves(moves) | Operation::AvoidMoves(moves) => moves
+                .iter()
+                .map(|mv| movegen.to_algebraic_notation(pos, mv))
+                .join(" "),
+            Operation::PredictedVariation(moves) => {
+                let mut pos = pos.clone();
+                let mut sans = Vec::with_capacity(moves.len());
+                for mv in moves {
+                    sans.push(movegen.to_algebraic_notation(&pos, mv));
+                    let _ = pos.make_move(mv);
+                }
+                sans.join(" ")
+            }
+            Operation::Id(text) | Operation::Comment(text) => format!("\"{text}\""),
+            Operation::CentipawnEval(value) => value.to_string(),
+            Operation::DirectMate(value) | Operation::AnalysisDepth(value) => value.to_string(),
+            Operation::Other(_, raw) => raw.clone(),
+        };
+
+        if operands.is_empty() {
+            format!("{};", self.opcode())
+        } else {
+            format!("{} {operands};", self.opcode())
+        }
+    }
+}
+
+impl Epd {
+    /// Parses a single EPD record. SAN moves in `bm`, `am` and `pv` are resolved against the
+    /// position, so an illegal or ambiguous move is an error.
+    pub fn parse(movegen: &MoveGen, line: &str) -> Result<Epd, EpdError> {
+        let mut rest = line.trim();
+        let mut fields = Vec::with_capacity(4);
+        for _ in 0..4 {
+            rest = rest.trim_start();
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            fields.push(&rest[..end]);
+            rest = &rest[end..];
+        }
+        let position = Position::from_fen_lenient(fields.join(" "))?;
+
+        let mut operations = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() {
+                break;
+            }
+
+            let end = rest
+                .find(|c: char| c.is_whitespace() || c == ';')
+                .unwrap_or(rest.len());
+            let opcode = &rest[..end];
+            rest = &rest[end..];
+
+            let (operands, raw, remaining) = split_operands(opcode, rest)?;
+            rest = remaining;
+
+            operations.push(Operation::parse(
+                movegen, &position, opcode, &operands, raw,
+            )?);
+        }
+
+        Ok(Epd {
+            position,
+            operations,
+        })
+    }
+
+    /// Writes the record back, with the first four FEN fields and all operations.
+    #[must_use]
+    pub fn to_epd(&self, movegen: &MoveGen) -> String {
+        let fen = self.position.to_fen();
+        let mut epd = fen.split_whitespace().take(4).join(" ");
+        for operation in &self.operations {
+            epd.push(' ');
+            epd.push_str(&operation.write(movegen, &self.position));
+        }
+
+        epd
+    }
+
+    #[must_use]
+    pub fn get(&self, opcode: &str) -> Option<&Operation> {
+        self.operations.iter().find(|op| op.opcode() == opcode)
+    }
+
+    /// Adds the operation, replacing an existing one with the same opcode. Used to fill in
+    /// engine results such as `ce`, `acd` and `pv` before writing the record out.
+    pub fn set(&mut self, operation: Operation) {
+        match self
+            .operations
+            .iter_mut()
+            .find(|op| op.opcode() == operation.opcode())
+        {
+            Some(existing) => *existing = operation,
+            None => self.operations.push(operation),
+        }
+    }
+}
+
+/// Splits the operands of `opcode` up to the terminating `;`. Returns the operands with quotes
+/// removed, the raw operand text and the rest of the line.
+fn split_operands<'a>(
+    opcode: &str,
+    line: &'a str,
+) -> Result<(Vec<String>, &'a str, &'a str), EpdError> {
+    let mut operands = Vec::new();
+    let mut current: Option<String> = None;
+    let mut in_string = false;
+    let mut end = line.len();
+
+    for (idx, c) in line.char_indices() {
+        match c {
+            '"' if in_string => {
+                operands.push(current.take().unwrap_or_default());
+                in_string = false;
+            }
+            '"' => {
+                if let Some(token) = current.take() {
+                    operands.push(token);
+                }
+                current = Some(String::new());
+                in_string = true;
+            }
+            ';' if !in_string => {
+                end = idx;
+                break;
+            }
+            c if c.is_whitespace() && !in_string => {
+                if let Some(token) = current.take() {
+                    operands.push(token);
+                }
+            }
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    if in_string {
+        return Err(EpdError::UnterminatedString(opcode.to_string()));
+    }
+    if let Some(token) = current {
+        operands.push(token);
+    }
+
+    let rest = line.get(end + 1..).unwrap_or("");
+    Ok((operands, line[..end].trim(), rest))
+}
+
+fn single_operand(opcode: &str, operands: &[String]) -> Result<String, EpdError> {
+    if operands.is_empty() {
+        return Err(EpdError::MissingOperand(opcode.to_string()));
+    }
+
+    Ok(operands.join(" "))
+}
+
+fn number_operand<T: FromStr>(opcode: &str, operands: &[String]) -> Result<T, EpdError> {
+    let operand = single_operand(opcode, operands)?;
+    operand
+        .parse()
+        .map_err(|_| EpdError::InvalidOperand(opcode.to_string(), operand))
+}
+
+fn resolve_moves(
+    movegen: &MoveGen,
+    pos: &Position,
+    sans: &[String],
+    sequential: bool,
+) -> Result<Vec<Move>, EpdError> {
+    let mut pos = pos.clone();
+    let mut moves = Vec::with_capacity(sans.len());
+
+    for san in sans {
+        let mv = movegen
+            .from_san(&pos, san)
+            .map_err(|_| EpdError::IllegalMove(san.clone()))?;
+        if sequential {
+            let _ = pos.make_move(&mv);
+        }
+        moves.push(mv);
+    }
+
+    Ok(moves)
+}
+
+#[cfg(test)]
+mod tests {
+    use sdk::square::Square;
+
+    use crate::{generators::movegen::MoveGen, r#move::Move, r#move::MoveKind};
+
+    use super::{Epd, EpdError, Operation};
+
+    #[test]
+    fn test_parse_epd() {
+        let movegen = MoveGen::new();
+        let epd = Epd::parse(
+            &movegen,
+            r#"2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001";"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            epd.operations,
+            vec![
+                Operation::BestMoves(vec![Move::new(
+                    Square::G3,
+                    Square::G6,
+                    None,
+                    &MoveKind::Quiet
+                )]),
+                Operation::Id("WAC.001".to_string()),
+            ]
+        );
+        assert_eq!(
+            epd.to_epd(&movegen),
+            r#"2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001";"#
+        );
+    }
+
+    #[test]
+    fn test_epd_operations() {
+        let movegen = MoveGen::new();
+        let mut epd = Epd::parse(
+            &movegen,
+            r#"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - bm e4 d4; am g4; c0 "1. e4; best by test"; ce 30; acd 12; pv e4 e5 Nf3; dm 3; hmvc 0;"#,
+        )
+        .unwrap();
+
+        assert_eq!(epd.operations.len(), 8);
+        let Some(Operation::BestMoves(bm)) = epd.get("bm") else {
+            panic!("Missing bm");
+        };
+        assert_eq!(bm.len(), 2);
+        assert_eq!(
+            epd.get("c0"),
+            Some(&Operation::Comment("1. e4; best by test".to_string()))
+        );
+        assert_eq!(epd.get("ce"), Some(&Operation::CentipawnEval(30)));
+        assert_eq!(epd.get("acd"), Some(&Operation::AnalysisDepth(12)));
+        assert_eq!(epd.get("dm"), Some(&Operation::DirectMate(3)));
+        assert_eq!(
+            epd.get("hmvc"),
+            Some(&Operation::Other("hmvc".to_string(), "0".to_string()))
+        );
+        let Some(Operation::PredictedVariation(pv)) = epd.get("pv") else {
+            panic!("Missing pv");
+        };
+        assert_eq!(
+            pv[2],
+            Move::new(Square::G1, Square::F3, None, &MoveKind::Quiet)
+        );
+
+        epd.set(Operation::CentipawnEval(-15));
+        epd.set(Operation::AnalysisDepth(20));
+        assert_eq!(
+            epd.to_epd(&movegen),
+            r#"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - bm e4 d4; am g4; c0 "1. e4; best by test"; ce -15; acd 20; pv e4 e5 Nf3; dm 3; hmvc 0;"#
+        );
+    }
+
+    #[test]
+    fn test_invalid_epd() {
+        let movegen = MoveGen::new();
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -";
+
+        assert_eq!(
+            Epd::parse(&movegen, &format!("{start} bm e5;")).unwrap_err(),
+            EpdError::IllegalMove("e5".to_string())
+        );
+        assert_eq!(
+            Epd::parse(&movegen, &format!("{start} ce x;")).unwrap_err(),
+            EpdError::InvalidOperand("ce".to_string(), "x".to_string())
+        );
+        assert_eq!(
+            Epd::parse(&movegen, &format!("{start} id \"unterminated;")).unwrap_err(),
+            EpdError::UnterminatedString("id".to_string())
+        );
+        assert!(matches!(
+            Epd::parse(&movegen, "8/8/8 w - - bm e4;"),
+            Err(EpdError::Fen(_))
+        ));
+    }
+}
@@ -1,2 +1,5 @@
+pub mod book;
 pub mod chess_notation;
+pub mod epd;
 pub mod logger;
+pub mod perft;
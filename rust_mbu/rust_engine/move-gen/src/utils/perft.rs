@@ -0,0 +1,285 @@
+//! Perft counts the leaf nodes of the legal move tree up to a given depth. Comparing the counts
+//! with known values is the standard way to find bugs in move generation and make/unmake.
+
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    thread,
+};
+
+use sdk::{position::Position, zeroed::ZeroedTable};
+
+use crate::{
+    generators::movegen::MoveGen,
+    r#move::{MakeMove, Move},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct PerftOptions {
+    /// Count the moves at the last ply instead of making each of them.
+    pub bulk: bool,
+    /// Number of threads the root moves are split between.
+    pub threads: usize,
+    /// Size of the table caching subtree counts in megabytes, 0 disables it.
+    pub hash_mb: usize,
+    /// Abandons the count once set, the counts returned then are incomplete.
+    pub stop: Option<&'static AtomicBool>,
+}
+
+impl Default for PerftOptions {
+    fn default() -> Self {
+        Self {
+            bulk: true,
+            threads: 1,
+            hash_mb: 0,
+            stop: None,
+        }
+    }
+}
+
+/// Subtree counts keyed by position hash and depth, shared between threads without locks. The
+/// key is stored xored with the data, so an entry torn by concurrent writes is seen as a miss.
+struct PerftCache {
+    entries: ZeroedTable<[AtomicU64; 2]>,
+}
+
+impl PerftCache {
+    const DEPTH_BITS: u32 = 8;
+
+    fn new(hash_mb: usize) -> Self {
+        let len = ((hash_mb << 20) / std::mem::size_of::<[AtomicU64; 2]>()).max(1);
+
+        Self {
+            entries: ZeroedTable::new(len),
+        }
+    }
+
+    fn entry(&self, hash: u64) -> &[AtomicU64; 2] {
+        &self.entries[(hash % self.entries.len() as u64) as usize]
+    }
+
+    fn probe(&self, hash: u64, depth: usize) -> Option<usize> {
+        let [key, data] = self.entry(hash);
+        let data = data.load(Ordering::Relaxed);
+        let key = key.load(Ordering::Relaxed);
+
+        (key ^ data == hash && data & ((1 << Self::DEPTH_BITS) - 1) == depth as u64)
+            .then_some((data >> Self::DEPTH_BITS) as usize)
+    }
+
+    fn store(&self, hash: u64, depth: usize, nodes: usize) {
+        let [key, data] = self.entry(hash);
+        let value = (nodes as u64) << Self::DEPTH_BITS | depth as u64;
+        key.store(hash ^ value, Ordering::Relaxed);
+        data.store(value, Ordering::Relaxed);
+    }
+}
+
+/// Number of leaf nodes `depth` plies below `pos`.
+#[must_use]
+pub fn perft(move_gen: &MoveGen, pos: &Position, depth: usize, options: &PerftOptions) -> usize {
+    if depth == 0 {
+        return 1;
+    }
+
+    divide(move_gen, pos, depth, options)
+        .iter()
+        .map(|(_, nodes)| nodes)
+        .sum()
+}
+
+/// Number of leaf nodes below each legal move of `pos`, in the order the moves are generated.
+///
+/// # Panics
+///
+/// Panics if a perft thread panics.
+#[must_use]
+pub fn divide(
+    move_gen: &MoveGen,
+    pos: &Position,
+    depth: usize,
+    options: &PerftOptions,
+) -> Vec<(Move, usize)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+
+    let moves = move_gen.generate_legal_moves(pos);
+    let cache = (options.hash_mb > 0).then(|| PerftCache::new(options.hash_mb));
+    let next_move = AtomicUsize::new(0);
+    let mut counts = vec![0; moves.len()];
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..options.threads.clamp(1, moves.len().max(1)))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut pos = pos.clone();
+                    let mut counts = Vec::new();
+
+                    loop {
+                        let idx = next_move.fetch_add(1, Ordering::Relaxed);
+                        let Some(mv) = moves.get(idx) else {
+                            break;
+                        };
+
+                        let undo = pos.make_move(mv).unwrap();
+                        let nodes = count(move_gen, &mut pos, depth - 1, options, cache.as_ref());
+                        pos.unmake_move(mv, &undo);
+                        counts.push((idx, nodes));
+                    }
+
+                    counts
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            for (idx, nodes) in worker.join().unwrap() {
+                counts[idx] = nodes;
+            }
+        }
+    });
+
+    moves.into_iter().zip(counts).collect()
+}
+
+fn count(
+    move_gen: &MoveGen,
+    pos: &mut Position,
+    depth: usize,
+    options: &PerftOptions,
+    cache: Option<&PerftCache>,
+) -> usize {
+    if depth == 0 {
+        return 1;
+    }
+
+    if options
+        .stop
+        .is_some_and(|stop| stop.load(Ordering::Relaxed))
+    {
+        return 0;
+    }
+
+    if let Some(nodes) = cache.and_then(|cache| cache.probe(pos.hash, depth)) {
+        return nodes;
+    }
+
+    let moves = move_gen.generate_legal_moves(pos);
+    if options.bulk && depth == 1 {
+        return moves.len();
+    }
+
+    let nodes = moves
+        .iter()
+        .map(|mv| {
+            let undo = pos.make_move(mv).unwrap();
+            let nodes = count(move_gen, pos, depth - 1, options, cache);
+            pos.unmake_move(mv, &undo);
+            nodes
+        })
+        .sum();
+
+    if let Some(cache) = cache {
+        cache.store(pos.hash, depth, nodes);
+    }
+
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use sdk::fen::Fen;
+
+    use super::*;
+
+    /// Standard perft positions with their node counts for depths 1 and up.
+    const PERFT_SUITE: [(&str, &[usize]); 6] = [
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &[20, 400, 8902, 197_281],
+        ),
+        (
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            &[48, 2039, 97862],
+        ),
+        (
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            &[14, 191, 2812, 43238, 674_624],
+        ),
+        (
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            &[6, 264, 9467, 422_333],
+        ),
+        (
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            &[44, 1486, 62379],
+        ),
+        (
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            &[46, 2079, 89890],
+        ),
+    ];
+
+    #[test]
+    fn test_perft_suite() {
+        let move_gen = MoveGen::new();
+        let options = PerftOptions {
+            threads: 4,
+            ..PerftOptions::default()
+        };
+
+        for (fen, expected) in PERFT_SUITE {
+            let pos = Position::from_fen(fen.to_string()).unwrap();
+
+            for (depth, nodes) in expected.iter().enumerate() {
+                assert_eq!(
+                    perft(&move_gen, &pos, depth + 1, &options),
+                    *nodes,
+                    "Fen: {fen}, depth: {}",
+                    depth + 1
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_perft_options_agree() {
+        let move_gen = MoveGen::new();
+        let pos = Position::from_fen(PERFT_SUITE[1].0.to_string()).unwrap();
+        let expected = PERFT_SUITE[1].1[2];
+
+        let divided = divide(&move_gen, &pos, 3, &PerftOptions::default());
+        assert_eq!(divided.len(), PERFT_SUITE[1].1[0]);
+        assert_eq!(
+            divided.iter().map(|(_, nodes)| nodes).sum::<usize>(),
+            expected
+        );
+
+        for options in [
+            PerftOptions {
+                bulk: false,
+                ..PerftOptions::default()
+            },
+            PerftOptions {
+                hash_mb: 1,
+                ..PerftOptions::default()
+            },
+            PerftOptions {
+                threads: 3,
+                hash_mb: 1,
+                ..PerftOptions::default()
+            },
+        ] {
+            assert_eq!(divide(&move_gen, &pos, 3, &options), divided, "{options:?}");
+        }
+
+        assert_eq!(perft(&move_gen, &pos, 0, &PerftOptions::default()), 1);
+
+        static STOP: AtomicBool = AtomicBool::new(true);
+        let options = PerftOptions {
+            stop: Some(&STOP),
+            ..PerftOptions::default()
+        };
+        assert_eq!(perft(&move_gen, &pos, 3, &options), 0);
+    }
+}
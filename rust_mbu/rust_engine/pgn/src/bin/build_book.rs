@@ -0,0 +1,106 @@
+//! Builds a Polyglot opening book from PGN files.
+//!
+//! `build_book [--min-elo N] [--min-count N] [--max-ply N] [--merge BOOK]... -o OUT GAMES.pgn...`
+//!
+//! Books given with `--merge` are added after the games, so positions found in the games keep
+//! their own moves.
+
+use std::{fs::File, io::BufReader, process::ExitCode};
+
+use move_gen::{generators::movegen::MoveGen, utils::book::Book};
+use pgn::{
+    book::{BookBuilder, BookOptions},
+    PgnReader,
+};
+
+const USAGE: &str = "Usage: build_book [--min-elo N] [--min-count N] [--max-ply N] \
+                     [--merge BOOK]... -o OUT GAMES.pgn...";
+
+struct Args {
+    options: BookOptions,
+    merge: Vec<String>,
+    output: String,
+    games: Vec<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut options = BookOptions::default();
+    let mut merge = Vec::new();
+    let mut output = None;
+    let mut games = Vec::new();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("Missing value for {arg}"));
+        match arg.as_str() {
+            "--min-elo" => options.min_elo = Some(parse_number(&value()?)?),
+            "--min-count" => options.min_count = parse_number(&value()?)?,
+            "--max-ply" => options.max_ply = parse_number(&value()?)?,
+            "--merge" => merge.push(value()?),
+            "-o" | "--output" => output = Some(value()?),
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {arg}")),
+            _ => games.push(arg),
+        }
+    }
+
+    Ok(Args {
+        options,
+        merge,
+        output: output.ok_or("Missing output file")?,
+        games,
+    })
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid number {value}"))
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let movegen = MoveGen::new();
+    let mut builder = BookBuilder::new(args.options);
+    let mut skipped = 0;
+
+    for path in &args.games {
+        let file = File::open(path).map_err(|e| format!("Couldn't open {path}: {e}"))?;
+        for game in PgnReader::new(BufReader::new(file), &movegen) {
+            match game {
+                Ok(game) => {
+                    if !builder.add_game(&game) {
+                        skipped += 1;
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{path}: {e}");
+                    skipped += 1;
+                }
+            }
+        }
+    }
+
+    let mut book = builder.build();
+    for path in &args.merge {
+        let other = Book::open(path).map_err(|e| format!("Couldn't read book {path}: {e}"))?;
+        book = book.merge(&other);
+    }
+
+    std::fs::write(&args.output, book.to_bytes())
+        .map_err(|e| format!("Couldn't write {}: {e}", args.output))?;
+    println!(
+        "{} games added, {skipped} skipped, {} entries written to {}",
+        builder.games(),
+        book.entries().len(),
+        args.output
+    );
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match parse_args(std::env::args().skip(1)).and_then(run) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+
+use move_gen::{
+    r#move::MakeMove,
+    utils::book::{Book, BookEntry},
+};
+use sdk::position::Color;
+
+use crate::game::{Game, GameResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookOptions {
+    /// Games where either player is rated below this, or isn't rated, are skipped.
+    pub min_elo: Option<u16>,
+    /// Moves played fewer times than this are left out of the book.
+    pub min_count: u32,
+    /// Only the first `max_ply` moves of every game are added.
+    pub max_ply: usize,
+}
+
+impl Default for BookOptions {
+    fn default() -> Self {
+        BookOptions {
+            min_elo: None,
+            min_count: 1,
+            max_ply: 30,
+        }
+    }
+}
+
+/// Results of a move from the point of view of the side that played it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct MoveStats {
+    wins: u32,
+    draws: u32,
+    losses: u32,
+}
+
+impl MoveStats {
+    const fn count(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Two points for a win and one for a draw, as in Polyglot.
+    const fn score(&self) -> u32 {
+        2 * self.wins + self.draws
+    }
+}
+
+/// Collects the moves of games into a Polyglot book.
+#[derive(Debug, Clone, Default)]
+pub struct BookBuilder {
+    pub options: BookOptions,
+    /// Keyed by Polyglot position key and encoded move.
+    stats: HashMap<(u64, u16), MoveStats>,
+    games: usize,
+}
+
+impl BookBuilder {
+    #[must_use]
+    pub fn new(options: BookOptions) -> BookBuilder {
+        BookBuilder {
+            options,
+            ..Default::default()
+        }
+    }
+
+    /// Number of games added so far.
+    #[must_use]
+    pub const fn games(&self) -> usize {
+        self.games
+    }
+
+    /// Adds the main line of the game. Returns `false` when the game is filtered out or has no
+    /// result.
+    pub fn add_game(&mut self, game: &Game) -> bool {
+        let winner = match game.result {
+            GameResult::WhiteWins => Some(Color::White),
+            GameResult::BlackWins => Some(Color::Black),
+            GameResult::Draw => None,
+            GameResult::Unknown => return false,
+        };
+
+        if let Some(min_elo) = self.options.min_elo {
+            let rated = |tag| {
+                game.tag(tag)
+                    .and_then(|elo| elo.parse::<u16>().ok())
+                    .is_some_and(|elo| elo >= min_elo)
+            };
+            if !rated("WhiteElo") || !rated("BlackElo") {
+                return false;
+            }
+        }
+
+        let mut pos = game.start.clone();
+        for mv in game.mainline().take(self.options.max_ply) {
+            let stats = self
+                .stats
+                .entry((pos.polyglot_key(), BookEntry::encode_move(&mv)))
+                .or_default();
+            match winner {
+                Some(color) if color == pos.turn => stats.wins += 1,
+                Some(_) => stats.losses += 1,
+                None => stats.draws += 1,
+            }
+
+            if pos.make_move(&mv).is_err() {
+                break;
+            }
+        }
+        self.games += 1;
+
+        true
+    }
+
+    /// Builds the book. Moves that never scored get no entry, and weights are scaled down per
+    /// position when they don't fit in 16 bits.
+    #[must_use]
+    pub fn build(&self) -> Book {
+        let mut by_position = HashMap::<u64, Vec<(u16, u32)>>::new();
+        for (&(key, mv), stats) in &self.stats {
+            if stats.count() >= self.options.min_count && stats.score() > 0 {
+                by_position
+                    .entry(key)
+                    .or_default()
+                    .push((mv, stats.score()));
+            }
+        }
+
+        let mut entries = Vec::new();
+        for (key, mut moves) in by_position {
+            let max_score = moves.iter().map(|(_, score)| *score).max().unwrap_or(0);
+            let divisor = max_score.div_ceil(u32::from(u16::MAX)).max(1);
+
+            moves.sort_unstable_by_key(|&(mv, score)| (std::cmp::Reverse(score), mv));
+            entries.extend(moves.into_iter().map(|(mv, score)| BookEntry {
+                key,
+                mv,
+                weight: u16::try_from(score / divisor).unwrap_or(u16::MAX).max(1),
+                learn: 0,
+            }));
+        }
+
+        Book::from_entries(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use move_gen::{
+        generators::movegen::MoveGen,
+        utils::{book::BookSelection, chess_notation::ChessNotation},
+    };
+    use sdk::position::Position;
+
+    use crate::{
+        book::{BookBuilder, BookOptions},
+        reader::parse_game,
+    };
+
+    #[test]
+    fn test_book_builder() {
+        let movegen = MoveGen::new();
+        let games = [
+            "[WhiteElo \"2000\"]\n[BlackElo \"2100\"]\n\n1. e4 e5 2. Nf3 1-0",
+            "[WhiteElo \"2000\"]\n[BlackElo \"2100\"]\n\n1. e4 c5 2. Nf3 0-1",
+            "[WhiteElo \"2000\"]\n[BlackElo \"2100\"]\n\n1. d4 d5 1/2-1/2",
+            "[WhiteElo \"2000\"]\n[BlackElo \"1500\"]\n\n1. c4 c5 1-0",
+            "[WhiteElo \"2000\"]\n[BlackElo \"2100\"]\n\n1. b3 e5 *",
+        ];
+
+        let mut builder = BookBuilder::new(BookOptions {
+            min_elo: Some(1800),
+            min_count: 1,
+            max_ply: 2,
+        });
+        let added = games
+            .iter()
+            .filter(|pgn| builder.add_game(&parse_game(&movegen, pgn).unwrap()))
+            .count();
+        assert_eq!(added, 3);
+
+        let book = builder.build();
+        let start = Position::default();
+        let e4 = movegen.from_san(&start, "e4").unwrap();
+        let d4 = movegen.from_san(&start, "d4").unwrap();
+
+        // e4 won once and lost once, d4 drew.
+        let moves = book.moves(&movegen, &start);
+        assert_eq!(moves, vec![(e4, 2), (d4, 1)]);
+        assert_eq!(book.choose(&movegen, &start, BookSelection::Best), Some(e4));
+        // White's second move is beyond the maximum ply, and a lost e5 scored nothing.
+        assert_eq!(book.entries().len(), 4);
+
+        builder.options.min_count = 2;
+        assert_eq!(builder.build().moves(&movegen, &start), vec![(e4, 2)]);
+    }
+}
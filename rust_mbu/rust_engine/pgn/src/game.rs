@@ -0,0 +1,172 @@
+use std::fmt::Display;
+
+use move_gen::r#move::{MakeMove, Move};
+use sdk::{fen::Fen, position::Position};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    /// `*`: game in progress, abandoned or unknown.
+    Unknown,
+}
+
+impl GameResult {
+    #[must_use]
+    pub fn from_token(token: &str) -> Option<GameResult> {
+        match token {
+            "1-0" => Some(GameResult::WhiteWins),
+            "0-1" => Some(GameResult::BlackWins),
+            "1/2-1/2" => Some(GameResult::Draw),
+            "*" => Some(GameResult::Unknown),
+            _ => None,
+        }
+    }
+}
+
+impl Display for GameResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameResult::WhiteWins => write!(f, "1-0"),
+            GameResult::BlackWins => write!(f, "0-1"),
+            GameResult::Draw => write!(f, "1/2-1/2"),
+            GameResult::Unknown => write!(f, "*"),
+        }
+    }
+}
+
+/// A move of the movetext together with its annotations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveNode {
+    pub mv: Move,
+    /// Numeric annotation glyphs, with `!`, `?`, `!!`, `??`, `!?` and `?!` stored as `$1`-`$6`.
+    pub nags: Vec<u8>,
+    /// Comments placed before the move.
+    pub comments_before: Vec<String>,
+    /// Comments placed after the move.
+    pub comments: Vec<String>,
+    /// Alternatives to this move, each played from the position before it.
+    pub variations: Vec<Vec<MoveNode>>,
+}
+
+impl MoveNode {
+    #[must_use]
+    pub fn new(mv: Move) -> MoveNode {
+        MoveNode {
+            mv,
+            nags: Vec::new(),
+            comments_before: Vec::new(),
+            comments: Vec::new(),
+            variations: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Game {
+    /// Tag pairs in the order they appeared.
+    pub tags: Vec<(String, String)>,
+    /// Starting position, taken from the `FEN` tag when present.
+    pub start: Position,
+    pub moves: Vec<MoveNode>,
+    pub result: GameResult,
+}
+
+impl Game {
+    /// Creates a game from the starting position with the tags of the seven tag roster.
+    #[must_use]
+    pub fn new() -> Game {
+        let tags = [
+            ("Event", "?"),
+            ("Site", "?"),
+            ("Date", "????.??.??"),
+            ("Round", "?"),
+            ("White", "?"),
+            ("Black", "?"),
+            ("Result", "*"),
+        ];
+
+        Game {
+            tags: tags
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            start: Position::default(),
+            moves: Vec::new(),
+            result: GameResult::Unknown,
+        }
+    }
+
+    #[must_use]
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Sets the tag, keeping its position if it already exists. Setting `FEN` does not change
+    /// the starting position.
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, existing)) => *existing = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// Starts the game from `position`, setting the `SetUp` and `FEN` tags.
+    pub fn set_start(&mut self, position: Position) {
+        self.set_tag("SetUp", "1");
+        self.set_tag("FEN", &position.to_fen());
+        self.start = position;
+    }
+
+    /// Moves of the main line.
+    pub fn mainline(&self) -> impl Iterator<Item = Move> + '_ {
+        self.moves.iter().map(|node| node.mv)
+    }
+
+    /// The starting position followed by the position after every main line move.
+    #[must_use]
+    pub fn positions(&self) -> Vec<Position> {
+        let mut positions = Vec::with_capacity(self.moves.len() + 1);
+        let mut pos = self.start.clone();
+        positions.push(pos.clone());
+
+        for mv in self.mainline() {
+            let _ = pos.make_move(&mv);
+            positions.push(pos.clone());
+        }
+
+        positions
+    }
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A game that could not be read. The reader skips it and continues with the next game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnError {
+    /// 1-based index of the game in the file.
+    pub game: usize,
+    /// 1-based line where the game starts.
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for PgnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Invalid PGN: game {} (line {}): {}",
+            self.game, self.line, self.message
+        )
+    }
+}
+
+impl std::error::Error for PgnError {}
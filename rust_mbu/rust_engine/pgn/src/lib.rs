@@ -0,0 +1,11 @@
+#![warn(clippy::pedantic)]
+#![allow(clippy::missing_errors_doc, clippy::missing_panics_doc)]
+
+pub mod book;
+pub mod game;
+pub mod reader;
+pub mod writer;
+
+pub use game::{Game, GameResult, MoveNode, PgnError};
+pub use reader::PgnReader;
+pub use writer::write_game;
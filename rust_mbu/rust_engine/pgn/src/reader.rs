@@ -0,0 +1,417 @@
+use std::io::BufRead;
+
+use move_gen::{
+    generators::movegen::MoveGen, r#move::MakeMove, utils::chess_notation::ChessNotation,
+};
+use sdk::{fen::Fen, position::Position};
+
+use crate::game::{Game, GameResult, MoveNode, PgnError};
+
+/// Reads games one at a time, so only a single game is kept in memory. A malformed game is
+/// returned as an error and reading continues with the next one.
+pub struct PgnReader<'a, R: BufRead> {
+    reader: R,
+    movegen: &'a MoveGen,
+    line: usize,
+    game: usize,
+    /// First line of the next game, read while looking for the end of the previous one.
+    pending: Option<(usize, String)>,
+}
+
+impl<'a, R: BufRead> PgnReader<'a, R> {
+    pub fn new(reader: R, movegen: &'a MoveGen) -> Self {
+        Self {
+            reader,
+            movegen,
+            line: 0,
+            game: 0,
+            pending: None,
+        }
+    }
+
+    /// Returns the text of the next game and the line it starts at.
+    fn next_game_text(&mut self) -> std::io::Result<Option<(usize, String)>> {
+        let mut text = String::new();
+        let mut start = 0;
+        let mut seen_movetext = false;
+        let mut in_comment = false;
+
+        loop {
+            let (line_no, line) = if let Some(pending) = self.pending.take() {
+                pending
+            } else {
+                let mut line = String::new();
+                if self.reader.read_line(&mut line)? == 0 {
+                    break;
+                }
+                self.line += 1;
+                (self.line, line)
+            };
+
+            let trimmed = line.trim();
+            if !in_comment {
+                if trimmed.starts_with('%') || (text.is_empty() && trimmed.is_empty()) {
+                    continue;
+                }
+                if trimmed.starts_with('[') && seen_movetext {
+                    self.pending = Some((line_no, line));
+                    break;
+                }
+                if !trimmed.is_empty() && !trimmed.starts_with('[') {
+                    seen_movetext = true;
+                }
+            }
+
+            if text.is_empty() {
+                start = line_no;
+            }
+            in_comment = ends_in_comment(trimmed, in_comment);
+            text.push_str(&line);
+            if !line.ends_with('\n') {
+                text.push('\n');
+            }
+        }
+
+        Ok((!text.is_empty()).then_some((start, text)))
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<'_, R> {
+    type Item = Result<Game, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (line, text) = match self.next_game_text() {
+            Ok(game) => game?,
+            Err(e) => {
+                self.game += 1;
+                return Some(Err(PgnError {
+                    game: self.game,
+                    line: self.line,
+                    message: e.to_string(),
+                }));
+            }
+        };
+        self.game += 1;
+
+        Some(parse_game(self.movegen, &text).map_err(|message| PgnError {
+            game: self.game,
+            line,
+            message,
+        }))
+    }
+}
+
+/// Parses a single game.
+pub fn parse_game(movegen: &MoveGen, text: &str) -> Result<Game, String> {
+    let tokens = tokenize(text)?;
+    let mut game = Game {
+        tags: Vec::new(),
+        start: Position::default(),
+        moves: Vec::new(),
+        result: GameResult::Unknown,
+    };
+
+    let mut idx = 0;
+    while let Some(Token::Tag(name, value)) = tokens.get(idx) {
+        game.tags.push((name.clone(), value.clone()));
+        idx += 1;
+    }
+
+    if let Some(fen) = game.tag("FEN") {
+        game.start = Position::from_fen_lenient(fen.to_string()).map_err(|e| e.to_string())?;
+    }
+    if let Some(result) = game.tag("Result").and_then(GameResult::from_token) {
+        game.result = result;
+    }
+
+    let mut parser = MoveTextParser {
+        movegen,
+        tokens: &tokens[idx..],
+        idx: 0,
+        result: None,
+    };
+    game.moves = parser.parse_line(&game.start, 0)?;
+    if let Some(result) = parser.result {
+        game.result = result;
+    }
+
+    Ok(game)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    VariationStart,
+    VariationEnd,
+    Nag(u8),
+    Result(GameResult),
+    San(String),
+}
+
+/// Tracks whether a `{` comment is still open at the end of the line.
+fn ends_in_comment(line: &str, mut in_comment: bool) -> bool {
+    for c in line.chars() {
+        match c {
+            '}' if in_comment => in_comment = false,
+            '{' if !in_comment => in_comment = true,
+            ';' if !in_comment => break,
+            _ => {}
+        }
+    }
+
+    in_comment
+}
+
+const fn suffix_nag(suffix: &str) -> Option<u8> {
+    Some(match suffix.as_bytes() {
+        b"!" => 1,
+        b"?" => 2,
+        b"!!" => 3,
+        b"??" => 4,
+        b"!?" => 5,
+        b"?!" => 6,
+        _ => return None,
+    })
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '[' => {
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                let mut name = String::new();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '"') {
+                    name.push(c);
+                }
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                if chars.next() != Some('"') {
+                    return Err(format!("Tag {name} has no value"));
+                }
+
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => value.extend(chars.next()),
+                        Some('"') => break,
+                        Some(c) => value.push(c),
+                        None => return Err(format!("Unterminated value of tag {name}")),
+                    }
+                }
+                if chars.by_ref().find(|c| !c.is_whitespace()) != Some(']') {
+                    return Err(format!("Tag {name} is not closed"));
+                }
+
+                tokens.push(Token::Tag(name, value));
+            }
+            '{' => {
+                let mut comment = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => comment.push(c),
+                        None => return Err("Unterminated comment".to_string()),
+                    }
+                }
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            ';' => {
+                let comment = chars
+                    .by_ref()
+                    .take_while(|c| *c != '\n')
+                    .collect::<String>();
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            '(' => tokens.push(Token::VariationStart),
+            ')' => tokens.push(Token::VariationEnd),
+            '$' => {
+                let mut nag = String::new();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    nag.push(digit);
+                }
+                let nag = nag.parse().map_err(|_| format!("Invalid NAG ${nag}"))?;
+                tokens.push(Token::Nag(nag));
+            }
+            c => {
+                let mut symbol = c.to_string();
+                while let Some(c) = chars.next_if(|c| {
+                    !c.is_whitespace() && !matches!(c, '{' | '}' | '(' | ')' | ';' | '$' | '[')
+                }) {
+                    symbol.push(c);
+                }
+                push_symbol(&mut tokens, &symbol)?;
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Splits a symbol such as `12.Nf3!?` into its move number, move and annotation.
+fn push_symbol(tokens: &mut Vec<Token>, symbol: &str) -> Result<(), String> {
+    if let Some(result) = GameResult::from_token(symbol) {
+        tokens.push(Token::Result(result));
+        return Ok(());
+    }
+
+    let symbol = symbol.trim_start_matches(|c: char| c.is_ascii_digit());
+    let symbol = symbol.trim_start_matches('.');
+    if symbol.is_empty() {
+        return Ok(());
+    }
+
+    let san = symbol.trim_end_matches(['!', '?']);
+    let suffix = &symbol[san.len()..];
+
+    if !san.is_empty() {
+        tokens.push(Token::San(san.to_string()));
+    }
+    if !suffix.is_empty() {
+        let nag = suffix_nag(suffix).ok_or(format!("Invalid annotation {suffix}"))?;
+        tokens.push(Token::Nag(nag));
+    }
+
+    Ok(())
+}
+
+struct MoveTextParser<'a> {
+    movegen: &'a MoveGen,
+    tokens: &'a [Token],
+    idx: usize,
+    result: Option<GameResult>,
+}
+
+impl MoveTextParser<'_> {
+    fn parse_line(&mut self, start: &Position, depth: usize) -> Result<Vec<MoveNode>, String> {
+        let mut nodes: Vec<MoveNode> = Vec::new();
+        let mut pos = start.clone();
+        let mut before_last = start.clone();
+        let mut comments_before = Vec::new();
+
+        while let Some(token) = self.tokens.get(self.idx) {
+            self.idx += 1;
+
+            match token {
+                Token::San(san) => {
+                    let mv = self
+                        .movegen
+                        .from_san(&pos, san)
+                        .map_err(|e| format!("{e} after {} plies", nodes.len()))?;
+
+                    let mut node = MoveNode::new(mv);
+                    node.comments_before = std::mem::take(&mut comments_before);
+                    nodes.push(node);
+
+                    before_last = pos.clone();
+                    let _ = pos.make_move(&mv);
+                }
+                Token::Comment(comment) => match nodes.last_mut() {
+                    Some(node) if comments_before.is_empty() => node.comments.push(comment.clone()),
+                    _ => comments_before.push(comment.clone()),
+                },
+                Token::Nag(nag) => nodes
+                    .last_mut()
+                    .ok_or(format!("Annotation ${nag} before any move"))?
+                    .nags
+                    .push(*nag),
+                Token::VariationStart => {
+                    if nodes.is_empty() {
+                        return Err("Variation before any move".to_string());
+                    }
+                    let variation = self.parse_line(&before_last, depth + 1)?;
+                    if let Some(node) = nodes.last_mut() {
+                        node.variations.push(variation);
+                    }
+                }
+                Token::VariationEnd if depth > 0 => return Ok(nodes),
+                Token::VariationEnd => return Err("Unbalanced ')'".to_string()),
+                Token::Result(result) if depth == 0 => {
+                    self.result = Some(*result);
+                    break;
+                }
+                Token::Result(_) => return Err("Result inside a variation".to_string()),
+                Token::Tag(name, _) => return Err(format!("Tag {name} inside movetext")),
+            }
+        }
+
+        if depth > 0 {
+            return Err("Unterminated variation".to_string());
+        }
+
+        Ok(nodes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use move_gen::generators::movegen::MoveGen;
+    use sdk::fen::Fen;
+
+    use crate::{game::GameResult, reader::PgnReader};
+
+    const GAMES: &str = r#"[Event "Casual"]
+[White "Anderssen, \"The Great\""]
+[Black "Kieseritzky"]
+[Result "1-0"]
+
+% escaped line
+1. e4 {King's pawn} e5 2. f4!? exf4 (2... d5 $1 {Falkbeer} 3. exd5 (3. Nf3) 3...
+e4) 3. Bc4 Qh4+ 4. Kf1 b5?! 1-0
+
+[Event"Broken"]
+[Result "*"]
+
+1. e4 e5 2. Ke3 *
+
+[Event "Setup"]
+[SetUp "1"]
+[FEN "4k3/8/8/8/8/8/8/4K2R w K - 0 30"]
+
+30. O-O Kd7 ; the king runs
+31. Rf7+ *
+"#;
+
+    #[test]
+    fn test_read_games() {
+        let movegen = MoveGen::new();
+        let games = PgnReader::new(Cursor::new(GAMES), &movegen).collect::<Vec<_>>();
+        assert_eq!(games.len(), 3);
+
+        let game = games[0].as_ref().unwrap();
+        assert_eq!(game.tag("White"), Some("Anderssen, \"The Great\""));
+        assert_eq!(game.result, GameResult::WhiteWins);
+        assert_eq!(game.moves.len(), 8);
+        assert_eq!(game.moves[0].comments, vec!["King's pawn".to_string()]);
+        assert_eq!(game.moves[2].nags, vec![5]);
+        assert_eq!(game.moves[7].nags, vec![6]);
+
+        let variation = &game.moves[3].variations[0];
+        assert_eq!(variation.len(), 3);
+        assert_eq!(variation[0].nags, vec![1]);
+        assert_eq!(variation[0].comments, vec!["Falkbeer".to_string()]);
+        assert_eq!(variation[1].variations[0].len(), 1);
+
+        let positions = game.positions();
+        assert_eq!(positions.len(), 9);
+        assert_eq!(
+            positions[8].to_fen(),
+            "rnb1kbnr/p1pp1ppp/8/1p6/2B1Pp1q/8/PPPP2PP/RNBQ1KNR w kq b6 0 5"
+        );
+
+        let error = games[1].as_ref().unwrap_err();
+        assert_eq!(error.game, 2);
+        assert_eq!(error.line, 10);
+
+        let game = games[2].as_ref().unwrap();
+        assert_eq!(game.moves.len(), 3);
+        assert_eq!(game.moves[1].comments, vec!["the king runs".to_string()]);
+        assert_eq!(game.start.fullmove_number, 30);
+        assert_eq!(game.result, GameResult::Unknown);
+    }
+}
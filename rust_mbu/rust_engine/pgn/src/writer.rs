@@ -0,0 +1,140 @@
+use std::io::{self, Write};
+
+use move_gen::{
+    generators::movegen::MoveGen, r#move::MakeMove, utils::chess_notation::ChessNotation,
+};
+use sdk::position::{Color, Position};
+
+use crate::game::{Game, MoveNode};
+
+/// Export format lines are at most 80 characters long.
+const MAX_LINE_LENGTH: usize = 80;
+
+/// Writes the game in PGN export format, followed by an empty line.
+pub fn write_game<W: Write>(writer: &mut W, movegen: &MoveGen, game: &Game) -> io::Result<()> {
+    let result = game.result.to_string();
+    for (name, value) in &game.tags {
+        let value = if name == "Result" { &result } else { value };
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        writeln!(writer, "[{name} \"{value}\"]")?;
+    }
+    writeln!(writer)?;
+
+    let mut tokens = Vec::new();
+    movetext(movegen, &game.start, &game.moves, &mut tokens);
+    tokens.push(result);
+
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + token.len() + 1 > MAX_LINE_LENGTH {
+            writeln!(writer, "{line}")?;
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    writeln!(writer, "{line}")?;
+    writeln!(writer)
+}
+
+#[must_use]
+pub fn to_pgn(movegen: &MoveGen, game: &Game) -> String {
+    let mut pgn = Vec::new();
+    write_game(&mut pgn, movegen, game).expect("Writing to a Vec can't fail");
+
+    String::from_utf8(pgn).expect("PGN is valid UTF-8")
+}
+
+fn movetext(movegen: &MoveGen, start: &Position, nodes: &[MoveNode], tokens: &mut Vec<String>) {
+    let mut pos = start.clone();
+    let mut needs_number = true;
+
+    for node in nodes {
+        for comment in &node.comments_before {
+            tokens.push(format!("{{{comment}}}"));
+            needs_number = true;
+        }
+
+        match pos.turn {
+            Color::White => tokens.push(format!("{}.", pos.fullmove_number)),
+            Color::Black if needs_number => tokens.push(format!("{}...", pos.fullmove_number)),
+            Color::Black => {}
+        }
+        tokens.push(movegen.to_algebraic_notation(&pos, &node.mv));
+
+        let before = pos.clone();
+        let _ = pos.make_move(&node.mv);
+        needs_number = false;
+
+        for nag in &node.nags {
+            tokens.push(format!("${nag}"));
+        }
+        for comment in &node.comments {
+            tokens.push(format!("{{{comment}}}"));
+            needs_number = true;
+        }
+        for variation in &node.variations {
+            let mut variation_tokens = Vec::new();
+            movetext(movegen, &before, variation, &mut variation_tokens);
+            if let Some(first) = variation_tokens.first_mut() {
+                first.insert(0, '(');
+            }
+            if let Some(last) = variation_tokens.last_mut() {
+                last.push(')');
+            }
+            tokens.extend(variation_tokens);
+            needs_number = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use move_gen::generators::movegen::MoveGen;
+
+    use crate::{
+        game::{Game, GameResult, MoveNode},
+        reader::PgnReader,
+        writer::to_pgn,
+    };
+
+    #[test]
+    fn test_write_game() {
+        let movegen = MoveGen::new();
+        let pgn = r#"[Event "Casual \"game\""]
+[Result "1-0"]
+
+1. e4 $1 {King's pawn} 1... e5 (1... c5 2. Nf3 (2. c3) 2... d6) 2. Nf3 Nc6 3.
+Bb5 a6 1-0
+
+"#;
+
+        let game = PgnReader::new(Cursor::new(pgn), &movegen)
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(to_pgn(&movegen, &game), pgn);
+    }
+
+    #[test]
+    fn test_write_new_game() {
+        let movegen = MoveGen::new();
+        let mut game = Game::new();
+        let mut pos = game.start.clone();
+        for mv in ["f3", "e5", "g4", "Qh4"] {
+            let mv = move_gen::utils::chess_notation::ChessNotation::from_san(&movegen, &pos, mv)
+                .unwrap();
+            move_gen::r#move::MakeMove::make_move(&mut pos, &mv).unwrap();
+            game.moves.push(MoveNode::new(mv));
+        }
+        game.result = GameResult::BlackWins;
+
+        let pgn = to_pgn(&movegen, &game);
+        assert!(pgn.contains("[Result \"0-1\"]"));
+        assert!(pgn.ends_with("\n\n1. f3 e5 2. g4 Qh4# 0-1\n\n"));
+    }
+}
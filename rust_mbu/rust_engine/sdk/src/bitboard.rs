@@ -54,9 +54,10 @@ impl Bitboard {
         Square::from_u8(idx)
     }
 
+    /// Panics if the bitboard is empty.
     #[must_use]
     pub const fn msb(&self) -> Square {
-        let idx = (63 - self.0.leading_zeros()) as u8;
+        let idx = self.0.ilog2() as u8;
 
         Square::from_u8(idx)
     }
@@ -1,176 +1,390 @@
-use anyhow::anyhow;
+use std::fmt::{Display, Write};
 
 use crate::{
     bitboard::Bitboard,
-    position::{Castling, Color, Piece, Position},
-    square::Square,
+    position::{
+        Castling, CastlingKind, Color, Piece, Position, PositionError, STANDARD_ROOK_FILES,
+    },
+    square::{File, Rank, Square},
 };
 
+/// Reasons for rejecting a FEN string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    /// A required field is missing, e.g. `"side to move"`.
+    MissingField(&'static str),
+    /// More than six whitespace separated fields.
+    TooManyFields(usize),
+    InvalidRankCount(usize),
+    /// A rank that does not describe exactly eight squares.
+    InvalidRank(String),
+    InvalidPiece(char),
+    InvalidTurn(String),
+    InvalidCastling(String),
+    InvalidEnPassant(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+    /// The FEN is well formed, but describes a position that cannot occur in a game.
+    IllegalPosition(Vec<PositionError>),
+}
+
+impl Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid FEN: ")?;
+        match self {
+            FenError::MissingField(field) => write!(f, "missing {field} field"),
+            FenError::TooManyFields(count) => write!(f, "expected 6 fields, got {count}"),
+            FenError::InvalidRankCount(count) => {
+                write!(f, "invalid number of ranks, got {count}, expected 8")
+            }
+            FenError::InvalidRank(rank) => write!(f, "rank {rank} does not have 8 squares"),
+            FenError::InvalidPiece(c) => write!(f, "invalid piece character {c}"),
+            FenError::InvalidTurn(turn) => write!(f, "invalid side to move {turn}"),
+            FenError::InvalidCastling(castling) => write!(f, "invalid castling field {castling}"),
+            FenError::InvalidEnPassant(ep) => write!(f, "invalid en passant square {ep}"),
+            FenError::InvalidHalfmoveClock(clock) => write!(f, "invalid halfmove clock {clock}"),
+            FenError::InvalidFullmoveNumber(number) => {
+                write!(f, "invalid fullmove number {number}")
+            }
+            FenError::IllegalPosition(errors) => {
+                let errors = errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+                write!(f, "{}", errors.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
 pub trait Fen {
-    fn from_fen(fen: String) -> anyhow::Result<Position>;
+    /// Parses FEN. The castling field may use standard `KQkq`, X-FEN or Shredder-FEN notation.
+    /// All six fields are required and the position must pass [`Position::validate`].
+    fn from_fen(fen: String) -> Result<Position, FenError>;
+    /// Like [`Fen::from_fen`], but the halfmove clock and fullmove number may be missing, in
+    /// which case they default to `0` and `1`.
+    fn from_fen_lenient(fen: String) -> Result<Position, FenError>;
+    /// Writes X-FEN, which is plain FEN for every standard chess position.
     fn to_fen(&self) -> String;
+    /// Writes Shredder-FEN, where castling rights are given by rook files (e.g. `HAha`).
+    fn to_shredder_fen(&self) -> String;
 }
 
 impl Fen for Position {
-    #[allow(clippy::too_many_lines)]
-    fn from_fen(fen: String) -> anyhow::Result<Position> {
-        let mut position = Position {
-            pieces: [[Bitboard(0); 6]; 2],
-            occupied: Bitboard(0),
-            turn: Color::White,
-            castling: Castling::empty(),
-            en_passant: None,
-            halfmove_clock: 0,
-            fullmove_number: 1,
-            hash: 0,
-            mailbox: [None; 64],
-        };
-        let mut fen = fen.split_whitespace();
-        let ranks = fen.next().unwrap().split('/');
-        let size = ranks.clone().count();
-        if size != 8 {
-            return Err(anyhow::anyhow!(
-                "Invalid FEN: Invalid number of ranks, got {}, expected 8",
-                size
-            ));
+    fn from_fen(fen: String) -> Result<Position, FenError> {
+        parse_fen(&fen, false)
+    }
+
+    fn from_fen_lenient(fen: String) -> Result<Position, FenError> {
+        parse_fen(&fen, true)
+    }
+
+    fn to_fen(&self) -> String {
+        to_fen(self, false)
+    }
+
+    fn to_shredder_fen(&self) -> String {
+        to_fen(self, true)
+    }
+}
+
+fn parse_fen(fen: &str, lenient: bool) -> Result<Position, FenError> {
+    let mut position = Position {
+        pieces: [[Bitboard(0); 6]; 2],
+        occupied: Bitboard(0),
+        turn: Color::White,
+        castling: Castling::empty(),
+        en_passant: None,
+        halfmove_clock: 0,
+        fullmove_number: 1,
+        hash: 0,
+        pawn_hash: 0,
+        material_key: 0,
+        mailbox: [None; 64],
+    };
+
+    let fields = fen.split_whitespace().collect::<Vec<_>>();
+    if fields.len() > 6 {
+        return Err(FenError::TooManyFields(fields.len()));
+    }
+    let field = |idx: usize, name: &'static str| {
+        fields.get(idx).copied().ok_or(FenError::MissingField(name))
+    };
+
+    parse_board(&mut position, field(0, "piece placement")?)?;
+
+    position.turn = match field(1, "side to move")? {
+        "w" => Color::White,
+        "b" => Color::Black,
+        turn => return Err(FenError::InvalidTurn(turn.to_string())),
+    };
+
+    position.castling = parse_castling(&position, field(2, "castling")?)?;
+    position.en_passant = parse_en_passant(field(3, "en passant")?)?;
+
+    match field(4, "halfmove clock") {
+        Ok(clock) => {
+            position.halfmove_clock = clock
+                .parse()
+                .map_err(|_| FenError::InvalidHalfmoveClock(clock.to_string()))?;
         }
+        Err(_) if lenient => {}
+        Err(e) => return Err(e),
+    }
 
-        for (rank, rank_str) in ranks.enumerate() {
-            let mut file = 0;
-            for c in rank_str.chars() {
-                if let Some(digit) = c.to_digit(10) {
-                    file += digit as usize;
-                } else {
-                    let color = if c.is_uppercase() {
-                        Color::White
-                    } else {
-                        Color::Black
-                    };
-
-                    let piece = match c.to_ascii_lowercase() {
-                        'p' => Piece::Pawn,
-                        'n' => Piece::Knight,
-                        'b' => Piece::Bishop,
-                        'r' => Piece::Rook,
-                        'q' => Piece::Queen,
-                        'k' => Piece::King,
-                        _ => {
-                            return Err(anyhow::anyhow!(
-                                "Invalid FEN: Invalid piece character {}",
-                                c
-                            ))
-                        }
-                    };
-
-                    let square: Square =
-                        Square::from_u8((7 - u8::try_from(rank)?) * 8 + u8::try_from(file)?);
-                    file += 1;
-                    let idx = piece as usize;
-
-                    position.pieces[color as usize][idx] |= square.bitboard();
-                    position.mailbox[square as usize] = Some((piece, color));
-                }
-            }
+    match field(5, "fullmove number") {
+        Ok(number) => {
+            position.fullmove_number = match number.parse() {
+                Ok(0) if lenient => 1,
+                Ok(0) | Err(_) => return Err(FenError::InvalidFullmoveNumber(number.to_string())),
+                Ok(number) => number,
+            };
         }
+        Err(_) if lenient => {}
+        Err(e) => return Err(e),
+    }
 
-        position.occupied = position.occupation(&Color::White) | position.occupation(&Color::Black);
-
-        let turn = fen.next().unwrap();
-        position.turn = match turn {
-            "w" => Color::White,
-            "b" => Color::Black,
-            _ => {
-                return Err(anyhow::anyhow!(
-                    "Invalid FEN: Invalid turn character {}",
-                    turn
-                ))
-            }
-        };
+    position.validate().map_err(FenError::IllegalPosition)?;
+    position.hash = position.calc_hash();
+    position.pawn_hash = position.calc_pawn_hash();
+    position.material_key = position.calc_material_key();
 
-        let castling: Castling = fen.next().unwrap().parse()?;
+    Ok(position)
+}
 
-        position.castling = castling;
+fn parse_board(position: &mut Position, board: &str) -> Result<(), FenError> {
+    let ranks = board.split('/').collect::<Vec<_>>();
+    if ranks.len() != 8 {
+        return Err(FenError::InvalidRankCount(ranks.len()));
+    }
 
-        let en_pass = fen.next().unwrap();
-        position.en_passant = if en_pass == "-" {
-            None
-        } else {
-            let mut chars = en_pass.chars();
-            let file_char = chars
-                .next()
-                .ok_or(anyhow!("Invalid FEN: Invalid en passant square: {en_pass}"))?;
-            let rank_char = chars
-                .next()
-                .ok_or(anyhow!("Invalid FEN: Invalid en passant square: {en_pass}"))?;
-            if (file_char as u8) < b'a' || (file_char as u8) > b'h' {
-                return Err(anyhow!("Invalid FEN: Invalid en passant file: {file_char}, expected a-h. En passant square: {en_pass}"));
+    for (rank, rank_str) in ranks.into_iter().enumerate() {
+        let invalid_rank = || FenError::InvalidRank(rank_str.to_string());
+        let mut file = 0;
+        for c in rank_str.chars() {
+            if let Some(digit) = c.to_digit(10) {
+                if !(1..=8).contains(&digit) {
+                    return Err(invalid_rank());
+                }
+                file += digit as u8;
+                if file > 8 {
+                    return Err(invalid_rank());
+                }
+                continue;
             }
-            if (rank_char as u8) < b'1' || (rank_char as u8) > b'8' {
-                return Err(anyhow!("Invalid FEN: Invalid en passant rank: {rank_char}, expected 1-8. En passant square: {en_pass}"));
+
+            let color = if c.is_ascii_uppercase() {
+                Color::White
+            } else {
+                Color::Black
+            };
+
+            let piece = match c.to_ascii_lowercase() {
+                'p' => Piece::Pawn,
+                'n' => Piece::Knight,
+                'b' => Piece::Bishop,
+                'r' => Piece::Rook,
+                'q' => Piece::Queen,
+                'k' => Piece::King,
+                _ => return Err(FenError::InvalidPiece(c)),
+            };
+
+            if file >= 8 {
+                return Err(invalid_rank());
             }
-            let file = file_char as u8 - b'a';
-            let rank = rank_char as u8 - b'1';
 
-            Some(Square::from_u8(rank * 8 + file))
-        };
+            let square = Square::from_u8((7 - rank as u8) * 8 + file);
+            file += 1;
+
+            position.pieces[color as usize][piece as usize] |= square.bitboard();
+            position.mailbox[square as usize] = Some((piece, color));
+        }
 
-        position.hash = position.calc_hash();
-        Ok(position)
+        if file != 8 {
+            return Err(invalid_rank());
+        }
     }
 
-    fn to_fen(&self) -> String {
-        let mut fen = String::new();
-        let mut empty = 0;
-        for rank in (0..8u8).rev() {
-            for file in 0..8u8 {
-                let square = Square::from_u8(rank * 8 + file);
-                let piece = self.piece_at(square);
-                if let Some((piece, color)) = piece {
-                    if empty != 0 {
-                        fen.push_str(&format!("{empty}"));
-                        empty = 0;
-                    }
-                    match color {
-                        Color::White => fen.push_str(&format!("{piece}").to_uppercase()),
-                        Color::Black => fen.push_str(&format!("{piece}").to_lowercase()),
-                    }
-                } else {
-                    empty += 1;
+    position.occupied = position.occupation(&Color::White) | position.occupation(&Color::Black);
+
+    Ok(())
+}
+
+fn parse_en_passant(en_passant: &str) -> Result<Option<Square>, FenError> {
+    if en_passant == "-" {
+        return Ok(None);
+    }
+
+    match en_passant.as_bytes() {
+        &[file @ b'a'..=b'h', rank @ b'1'..=b'8'] => Ok(Some(Square::from_file_rank(
+            File::from_u8(file - b'a'),
+            Rank::from_u8(rank - b'1'),
+        ))),
+        _ => Err(FenError::InvalidEnPassant(en_passant.to_string())),
+    }
+}
+
+fn to_fen(pos: &Position, shredder: bool) -> String {
+    let mut fen = String::new();
+    let mut empty = 0;
+    for rank in (0..8u8).rev() {
+        for file in 0..8u8 {
+            let square = Square::from_u8(rank * 8 + file);
+            let piece = pos.piece_at(square);
+            if let Some((piece, color)) = piece {
+                if empty != 0 {
+                    let _ = write!(fen, "{empty}");
+                    empty = 0;
+                }
+                match color {
+                    Color::White => fen.push_str(&format!("{piece}").to_uppercase()),
+                    Color::Black => fen.push_str(&format!("{piece}").to_lowercase()),
                 }
+            } else {
+                empty += 1;
             }
-            if empty != 0 {
-                fen.push_str(&format!("{empty}"));
-                empty = 0;
+        }
+        if empty != 0 {
+            let _ = write!(fen, "{empty}");
+            empty = 0;
+        }
+        if rank != 0 {
+            fen.push('/');
+        }
+    }
+
+    let _ = write!(fen, " {} ", pos.turn);
+    fen.push_str(&castling_to_fen(pos, shredder));
+    fen.push(' ');
+    if let Some(square) = &pos.en_passant {
+        fen.push_str(&square.coords_str());
+    } else {
+        fen.push('-');
+    }
+    let _ = write!(fen, " {} {}", pos.halfmove_clock, pos.fullmove_number);
+
+    fen
+}
+
+const fn back_rank(color: Color) -> Rank {
+    match color {
+        Color::White => Rank::R1,
+        Color::Black => Rank::R8,
+    }
+}
+
+/// Returns the file of the outermost rook of `color` on the given side of its king.
+fn outermost_rook_file(pos: &Position, color: Color, kingside: bool) -> Option<File> {
+    let rank = back_rank(color).bitboard();
+    let king = pos.pieces[color as usize][Piece::King as usize] & rank;
+    if king.is_empty() {
+        return None;
+    }
+    let king_file = king.lsb().file();
+    let rooks = (pos.pieces[color as usize][Piece::Rook as usize] & rank)
+        .into_iter()
+        .map(|sq| sq.file());
+
+    if kingside {
+        rooks.filter(|file| *file > king_file).max()
+    } else {
+        rooks.filter(|file| *file < king_file).min()
+    }
+}
+
+/// Parses the castling field. `KQkq` pick the outermost rook on the given side of the king
+/// (X-FEN), while file letters `A-H`/`a-h` pick the rook explicitly (Shredder-FEN).
+fn parse_castling(pos: &Position, castling: &str) -> Result<Castling, FenError> {
+    let mut result = Castling::empty();
+    if castling == "-" {
+        return Ok(result);
+    }
+
+    for c in castling.chars() {
+        let color = if c.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+
+        let (kind, file) = match c.to_ascii_lowercase() {
+            side @ ('k' | 'q') => {
+                let kind = CastlingKind::new(color, side == 'k');
+                let file = outermost_rook_file(pos, color, kind.is_kingside())
+                    .unwrap_or(STANDARD_ROOK_FILES[kind as usize]);
+
+                (kind, file)
             }
-            if rank != 0 {
-                fen.push('/');
+            file @ 'a'..='h' => {
+                let file = File::from_u8(file as u8 - b'a');
+                let king =
+                    pos.pieces[color as usize][Piece::King as usize] & back_rank(color).bitboard();
+                let kingside = if king.is_empty() {
+                    file > File::E
+                } else {
+                    file > king.lsb().file()
+                };
+
+                (CastlingKind::new(color, kingside), file)
             }
+            _ => return Err(FenError::InvalidCastling(castling.to_string())),
+        };
+
+        result.set_castling_rook(&kind, file);
+    }
+
+    Ok(result)
+}
+
+/// Castling field of the FEN, with Shredder-FEN rook files if `shredder` is set and X-FEN
+/// otherwise, where file letters are only used for rooks that aren't the outermost ones.
+#[must_use]
+pub fn castling_to_fen(pos: &Position, shredder: bool) -> String {
+    let mut result = String::new();
+
+    for kind in CastlingKind::all() {
+        if !pos.castling.has_castling_kind(&kind) {
+            continue;
         }
 
-        fen.push(' ');
-        fen.push_str(&format!("{}", self.turn));
-        fen.push(' ');
-        fen.push_str(self.castling.to_string().as_str());
-        fen.push(' ');
-        if let Some(square) = &self.en_passant {
-            fen.push_str(&square.coords_str());
+        let file = pos.castling.rook_files[kind as usize];
+        let is_outermost = outermost_rook_file(pos, kind.color(), kind.is_kingside())
+            .map_or(file == STANDARD_ROOK_FILES[kind as usize], |outer| {
+                outer == file
+            });
+
+        let symbol = if !shredder && is_outermost {
+            if kind.is_kingside() {
+                'k'
+            } else {
+                'q'
+            }
         } else {
-            fen.push('-');
+            char::from(b'a' + file as u8)
+        };
+
+        match kind.color() {
+            Color::White => result.push(symbol.to_ascii_uppercase()),
+            Color::Black => result.push(symbol),
         }
-        fen.push(' ');
-        fen.push_str(&format!("{}", self.halfmove_clock));
-        fen.push(' ');
-        fen.push_str(&format!("{}", self.fullmove_number));
+    }
 
-        fen
+    if result.is_empty() {
+        result.push('-');
     }
+
+    result
 }
 
 #[cfg(test)]
 mod tests {
 
-    use crate::fen::Fen;
-    use crate::position::Position;
+    use crate::fen::{Fen, FenError};
+    use crate::position::{CastlingKind, Color, Position, PositionError};
+    use crate::square::Square;
+
+    fn fen_error(fen: &str) -> FenError {
+        Position::from_fen(fen.to_string()).unwrap_err()
+    }
 
     #[test]
     fn test_starting_fen() {
@@ -180,5 +394,134 @@ mod tests {
             starting_pos.to_fen(),
             "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
         );
+        assert_eq!(
+            starting_pos.to_shredder_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1"
+        );
+    }
+
+    #[test]
+    fn test_chess960_castling_fen() {
+        let shredder = "1r2kr2/8/8/8/8/8/8/1R1RK1R1 w GDfb - 0 1";
+        let pos = Position::from_fen(shredder.to_string()).unwrap();
+
+        assert_eq!(
+            pos.castling.rook_square(&CastlingKind::WhiteKingside),
+            Square::G1
+        );
+        assert_eq!(
+            pos.castling.rook_square(&CastlingKind::WhiteQueenside),
+            Square::D1
+        );
+        assert_eq!(
+            pos.castling.rook_square(&CastlingKind::BlackKingside),
+            Square::F8
+        );
+        assert_eq!(
+            pos.castling.rook_square(&CastlingKind::BlackQueenside),
+            Square::B8
+        );
+
+        // The white queenside rook is not the outermost one, so X-FEN needs its file.
+        assert_eq!(pos.to_fen(), "1r2kr2/8/8/8/8/8/8/1R1RK1R1 w KDkq - 0 1");
+        assert_eq!(pos.to_shredder_fen(), shredder);
+        assert!(pos.to_string().contains("Castling: KDkq\n"));
+
+        let x_fen = Position::from_fen(pos.to_fen()).unwrap();
+        assert_eq!(x_fen.castling.rook_files, pos.castling.rook_files);
+        assert_eq!(x_fen.hash, pos.hash);
+    }
+
+    #[test]
+    fn test_malformed_fen() {
+        assert_eq!(fen_error(""), FenError::MissingField("piece placement"));
+        assert_eq!(
+            fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1"),
+            FenError::InvalidRankCount(7)
+        );
+        assert_eq!(
+            fen_error("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            FenError::InvalidRank("9".to_string())
+        );
+        assert_eq!(
+            fen_error("rnbqkbnr/ppppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            FenError::InvalidRank("ppppppppp".to_string())
+        );
+        assert_eq!(
+            fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1"),
+            FenError::InvalidPiece('X')
+        );
+        assert_eq!(
+            fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1"),
+            FenError::InvalidTurn("x".to_string())
+        );
+        assert_eq!(
+            fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1"),
+            FenError::InvalidCastling("KQkx".to_string())
+        );
+        assert_eq!(
+            fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9 0 1"),
+            FenError::InvalidEnPassant("e9".to_string())
+        );
+        assert_eq!(
+            fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1"),
+            FenError::InvalidHalfmoveClock("x".to_string())
+        );
+        assert_eq!(
+            fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 x"),
+            FenError::TooManyFields(7)
+        );
+    }
+
+    #[test]
+    fn test_lenient_fen() {
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3";
+        assert_eq!(fen_error(fen), FenError::MissingField("halfmove clock"));
+
+        let pos = Position::from_fen_lenient(fen.to_string()).unwrap();
+        assert_eq!(pos.halfmove_clock, 0);
+        assert_eq!(pos.fullmove_number, 1);
+        assert_eq!(pos.en_passant, Some(Square::E3));
+
+        let pos = Position::from_fen_lenient(format!("{fen} 3 12")).unwrap();
+        assert_eq!(pos.halfmove_clock, 3);
+        assert_eq!(pos.fullmove_number, 12);
+        assert!(Position::from_fen_lenient("8/8/8/8/8/8/8/8 w".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_illegal_positions() {
+        assert_eq!(
+            fen_error("8/8/8/8/8/8/8/4K3 w - - 0 1"),
+            FenError::IllegalPosition(vec![PositionError::MissingKing(Color::Black)])
+        );
+        assert_eq!(
+            fen_error("4k3/8/8/8/8/8/8/P3K3 w - - 0 1"),
+            FenError::IllegalPosition(vec![PositionError::PawnOnBackRank(Square::A1)])
+        );
+        assert_eq!(
+            fen_error("4k3/8/8/8/8/8/4P3/4K3 b - e3 0 1"),
+            FenError::IllegalPosition(vec![PositionError::InvalidEnPassant(Square::E3)])
+        );
+        assert_eq!(
+            fen_error("4k3/8/8/8/8/8/8/4K2R w Q - 0 1"),
+            FenError::IllegalPosition(vec![PositionError::InvalidCastlingRights(
+                CastlingKind::WhiteQueenside
+            )])
+        );
+        assert_eq!(
+            fen_error("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1"),
+            FenError::IllegalPosition(vec![PositionError::OpponentInCheck])
+        );
+
+        // Every problem is reported, not just the first one.
+        assert_eq!(
+            fen_error("p7/8/8/8/8/8/8/4K3 w k - 0 1"),
+            FenError::IllegalPosition(vec![
+                PositionError::MissingKing(Color::Black),
+                PositionError::PawnOnBackRank(Square::A8),
+                PositionError::InvalidCastlingRights(CastlingKind::BlackKingside),
+            ])
+        );
     }
 }
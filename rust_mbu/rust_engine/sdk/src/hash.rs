@@ -1,14 +1,16 @@
 use core::array::from_fn;
-use lazy_static::lazy_static;
 use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::sync::LazyLock;
 
-use crate::position::{Color, Position};
+use crate::{
+    polyglot::{CASTLING_OFFSET, EN_PASSANT_OFFSET, POLYGLOT_KEYS, TURN_OFFSET},
+    position::{CastlingKind, Color, Piece, Position},
+};
 
-lazy_static! {
-    pub static ref ZOBRIST_KEYS: ZobristKeys = ZobristKeys::default();
-}
+pub static ZOBRIST_KEYS: LazyLock<ZobristKeys> = LazyLock::new(ZobristKeys::default);
 
 pub struct ZobristKeys {
+    /// Indexed by square. [`Position::material_key`] reuses them indexed by piece count instead.
     pub pieces: [[[u64; 64]; 6]; 2],
     pub castling_rights: [u64; 16],
     pub en_passant: [u64; 64],
@@ -33,6 +35,21 @@ impl Default for ZobristKeys {
     }
 }
 
+impl ZobristKeys {
+    /// Digest of all keys, which tells whether hashes saved elsewhere were made with these keys.
+    #[must_use]
+    pub fn fingerprint(&self) -> u64 {
+        self.pieces
+            .iter()
+            .flatten()
+            .flatten()
+            .chain(&self.castling_rights)
+            .chain(&self.en_passant)
+            .chain([&self.side_to_move])
+            .fold(0, |acc, key| acc.rotate_left(1) ^ key)
+    }
+}
+
 impl Position {
     #[must_use]
     pub fn calc_hash(&self) -> u64 {
@@ -56,4 +73,129 @@ impl Position {
 
         hash
     }
+
+    /// Zobrist hash of the pawns of both sides only.
+    #[must_use]
+    pub fn calc_pawn_hash(&self) -> u64 {
+        let mut hash = 0;
+
+        for color in 0..2 {
+            for sq in self.pieces[color][Piece::Pawn as usize] {
+                hash ^= ZOBRIST_KEYS.pieces[color][Piece::Pawn as usize][sq as usize];
+            }
+        }
+
+        hash
+    }
+
+    /// Key identifying the material signature, i.e. the number of pieces of every kind and color.
+    #[must_use]
+    pub fn calc_material_key(&self) -> u64 {
+        let mut key = 0;
+
+        for color in 0..2 {
+            for piece in 0..6 {
+                for count in 0..self.pieces[color][piece].count() {
+                    key ^= ZOBRIST_KEYS.pieces[color][piece][count as usize];
+                }
+            }
+        }
+
+        key
+    }
+
+    /// Key of the position in Polyglot opening books. Unlike [`Position::calc_hash`], the en
+    /// passant file only counts when a pawn of the side to move can capture there.
+    #[must_use]
+    pub fn polyglot_key(&self) -> u64 {
+        let mut key = 0;
+
+        for color in [Color::White, Color::Black] {
+            for piece in Piece::all() {
+                let kind = 2 * piece as usize + usize::from(color == Color::White);
+                for sq in self.pieces[color as usize][piece as usize] {
+                    key ^= POLYGLOT_KEYS[64 * kind + sq as usize];
+                }
+            }
+        }
+
+        for (idx, kind) in CastlingKind::all().iter().enumerate() {
+            if self.castling.has_castling_kind(kind) {
+                key ^= POLYGLOT_KEYS[CASTLING_OFFSET + idx];
+            }
+        }
+
+        if let Some(en_passant) = self.en_passant {
+            let our_pawns = self.pieces[self.turn as usize][Piece::Pawn as usize];
+            let rank_offset = if self.turn == Color::White { -1 } else { 1 };
+            let can_capture = [-1, 1].into_iter().any(|file_offset| {
+                en_passant
+                    .offset(rank_offset, file_offset)
+                    .is_some_and(|sq| our_pawns.has(sq))
+            });
+
+            if can_capture {
+                key ^= POLYGLOT_KEYS[EN_PASSANT_OFFSET + en_passant.file() as usize];
+            }
+        }
+
+        if self.turn == Color::White {
+            key ^= POLYGLOT_KEYS[TURN_OFFSET];
+        }
+
+        key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{fen::Fen, position::Position};
+
+    #[test]
+    fn test_polyglot_key() {
+        // Test positions from the Polyglot book format specification.
+        let positions = [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                0x463b_9618_1691_fc9c,
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+                0x823c_9b50_fd11_4196,
+            ),
+            (
+                "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+                0x0756_b944_61c5_0fb0,
+            ),
+            (
+                "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2",
+                0x662f_afb9_65db_29d4,
+            ),
+            (
+                "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+                0x22a4_8b5a_8e47_ff78,
+            ),
+            (
+                "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR b kq - 0 3",
+                0x652a_607c_a3f2_42c1,
+            ),
+            (
+                "rnbq1bnr/ppp1pkpp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR w - - 0 4",
+                0x00fd_d303_c946_bdd9,
+            ),
+            (
+                "rnbqkbnr/p1pppppp/8/8/PpP4P/8/1P1PPPP1/RNBQKBNR b KQkq c3 0 3",
+                0x3c81_23ea_7b06_7637,
+            ),
+            (
+                "rnbqkbnr/p1pppppp/8/8/P6P/R1p5/1P1PPPP1/1NBQKBNR b Kkq - 0 4",
+                0x5c3f_9b82_9b27_9560,
+            ),
+        ];
+
+        for (fen, key) in positions {
+            let pos = Position::from_fen(fen.to_string()).unwrap();
+            assert_eq!(pos.polyglot_key(), key, "{fen}");
+        }
+    }
 }
@@ -13,5 +13,7 @@ pub mod bitboard;
 pub mod fen;
 pub mod hash;
 pub mod lookup;
+pub mod polyglot;
 pub mod position;
 pub mod square;
+pub mod zeroed;
@@ -4,6 +4,7 @@ use crate::{
     square::{Rank, Square},
 };
 
+#[must_use]
 pub fn generate_passers() -> Vec<Vec<Bitboard>> {
     let mut result = vec![vec![Bitboard::empty(); 64]; 2];
     for color in Color::iter() {
@@ -20,12 +20,12 @@ impl From<Slider> for Piece {
     }
 }
 
-impl ToString for Slider {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for Slider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Slider::Bishop => "Bishop".to_string(),
-            Slider::Rook => "Rook".to_string(),
-            Slider::Queen => "Queen".to_string(),
+            Slider::Bishop => write!(f, "Bishop"),
+            Slider::Rook => write!(f, "Rook"),
+            Slider::Queen => write!(f, "Queen"),
         }
     }
 }
@@ -1,14 +1,23 @@
-use std::{fmt::Display, str::FromStr};
+use std::fmt::Display;
 
 use anyhow::anyhow;
 use derivative::Derivative;
 
 use crate::{
     bitboard::Bitboard,
-    fen::Fen,
-    square::{Square, FILE_MASKS},
+    fen::{castling_to_fen, Fen},
+    lookup::{
+        king::mask_king_attacks,
+        knights::mask_knights_attacks,
+        pawns::mask_pawns_attacks,
+        sliders::{mask_slider_attacks_occ, Slider},
+    },
+    square::{File, Rank, Square, FILE_MASKS},
 };
 
+/// Rook files of standard chess, indexed by `CastlingKind as usize`.
+pub const STANDARD_ROOK_FILES: [File; 4] = [File::H, File::A, File::H, File::A];
+
 #[derive(Derivative, Debug, Clone)]
 #[derivative(Hash)]
 pub struct Position {
@@ -26,6 +35,10 @@ pub struct Position {
     #[derivative(PartialEq = "ignore")]
     pub fullmove_number: u16,
     pub hash: u64,
+    #[derivative(Hash = "ignore")]
+    pub pawn_hash: u64,
+    #[derivative(Hash = "ignore")]
+    pub material_key: u64,
     pub mailbox: [Option<(Piece, Color)>; 64],
 }
 
@@ -48,8 +61,12 @@ pub enum Piece {
 #[derive(Debug, Clone, Hash)]
 pub struct Castling {
     pub inner: u8,
+    /// Starting files of the castling rooks, indexed by `CastlingKind as usize`. Standard chess
+    /// always uses the a- and h-files, Chess960 may use any file on either side of the king.
+    pub rook_files: [File; 4],
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CastlingKind {
     WhiteKingside,
     WhiteQueenside,
@@ -57,27 +74,49 @@ pub enum CastlingKind {
     BlackQueenside,
 }
 
-impl FromStr for Castling {
-    type Err = anyhow::Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s == "-" {
-            return Ok(Castling { inner: 0 });
-        }
+/// Problems found by [`Position::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PositionError {
+    MissingKing(Color),
+    TooManyKings(Color),
+    TooManyPawns(Color),
+    TooManyPieces(Color),
+    PawnOnBackRank(Square),
+    /// The king or the rook needed for this castling right is not on its square.
+    InvalidCastlingRights(CastlingKind),
+    /// The en passant square is not behind a pawn that has just made a double push.
+    InvalidEnPassant(Square),
+    /// The side that has just moved left its king in check.
+    OpponentInCheck,
+}
 
-        let mut inner = 0u8;
+impl Display for PositionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let color_name = |color: &Color| match color {
+            Color::White => "white",
+            Color::Black => "black",
+        };
 
-        for c in s.chars() {
-            match c {
-                'K' => inner |= 0b1000,
-                'Q' => inner |= 0b0100,
-                'k' => inner |= 0b0010,
-                'q' => inner |= 0b0001,
-                _ => return Err(anyhow!("Invalid castling string: {}", s)),
+        match self {
+            PositionError::MissingKing(color) => write!(f, "{} king is missing", color_name(color)),
+            PositionError::TooManyKings(color) => {
+                write!(f, "{} has more than one king", color_name(color))
+            }
+            PositionError::TooManyPawns(color) => {
+                write!(f, "{} has more than 8 pawns", color_name(color))
+            }
+            PositionError::TooManyPieces(color) => {
+                write!(f, "{} has more than 16 pieces", color_name(color))
             }
+            PositionError::PawnOnBackRank(square) => write!(f, "pawn on {square}"),
+            PositionError::InvalidCastlingRights(kind) => {
+                write!(f, "castling right {kind:?} without its king and rook")
+            }
+            PositionError::InvalidEnPassant(square) => {
+                write!(f, "en passant square {square} without a double pushed pawn")
+            }
+            PositionError::OpponentInCheck => write!(f, "side not to move is in check"),
         }
-
-        Ok(Castling { inner })
     }
 }
 
@@ -91,20 +130,63 @@ impl From<usize> for Color {
     }
 }
 
-impl From<Square> for CastlingKind {
-    fn from(value: Square) -> Self {
-        match value {
-            Square::G1 => CastlingKind::WhiteKingside,
-            Square::C1 => CastlingKind::WhiteQueenside,
-            Square::G8 => CastlingKind::BlackKingside,
-            Square::C8 => CastlingKind::BlackQueenside,
-            _ => panic!("Invalid castling square: {value}"),
+impl CastlingKind {
+    #[must_use]
+    pub const fn all() -> [CastlingKind; 4] {
+        [
+            CastlingKind::WhiteKingside,
+            CastlingKind::WhiteQueenside,
+            CastlingKind::BlackKingside,
+            CastlingKind::BlackQueenside,
+        ]
+    }
+
+    #[must_use]
+    pub const fn new(color: Color, kingside: bool) -> CastlingKind {
+        match (color, kingside) {
+            (Color::White, true) => CastlingKind::WhiteKingside,
+            (Color::White, false) => CastlingKind::WhiteQueenside,
+            (Color::Black, true) => CastlingKind::BlackKingside,
+            (Color::Black, false) => CastlingKind::BlackQueenside,
         }
     }
-}
 
-impl CastlingKind {
-    /// Returns tuple of `(rook_target_square, king_target_square)`.
+    #[must_use]
+    pub const fn color(&self) -> Color {
+        match self {
+            CastlingKind::WhiteKingside | CastlingKind::WhiteQueenside => Color::White,
+            CastlingKind::BlackKingside | CastlingKind::BlackQueenside => Color::Black,
+        }
+    }
+
+    #[must_use]
+    pub const fn is_kingside(&self) -> bool {
+        matches!(
+            self,
+            CastlingKind::WhiteKingside | CastlingKind::BlackKingside
+        )
+    }
+
+    #[must_use]
+    const fn mask(self) -> u8 {
+        match self {
+            CastlingKind::WhiteKingside => 0b1000,
+            CastlingKind::WhiteQueenside => 0b0100,
+            CastlingKind::BlackKingside => 0b0010,
+            CastlingKind::BlackQueenside => 0b0001,
+        }
+    }
+
+    #[must_use]
+    const fn back_rank(self) -> Rank {
+        match self.color() {
+            Color::White => Rank::R1,
+            Color::Black => Rank::R8,
+        }
+    }
+
+    /// Returns tuple of `(rook_target_square, king_target_square)`. These are the same in
+    /// standard chess and Chess960.
     #[must_use]
     pub fn target_squares(&self) -> (Square, Square) {
         match self {
@@ -115,37 +197,37 @@ impl CastlingKind {
         }
     }
 
-    /// Returns tuple of `(rook_from_square, king_from_square)`.
+    /// Returns tuple of `(rook_from_square, king_from_square)`, taking the rook file from the
+    /// castling state and the king square from the position.
     #[must_use]
-    pub fn from_squares(&self) -> (Square, Square) {
-        match self {
-            CastlingKind::WhiteKingside => (Square::H1, Square::E1),
-            CastlingKind::WhiteQueenside => (Square::A1, Square::E1),
-            CastlingKind::BlackKingside => (Square::H8, Square::E8),
-            CastlingKind::BlackQueenside => (Square::A8, Square::E8),
-        }
+    pub fn from_squares(&self, pos: &Position) -> (Square, Square) {
+        let rook = pos.castling.rook_square(self);
+        let king = pos.pieces[self.color() as usize][Piece::King as usize].lsb();
+
+        (rook, king)
     }
 }
 
 impl Castling {
     #[must_use]
     pub const fn full() -> Castling {
-        Castling { inner: 0b1111 }
+        Castling {
+            inner: 0b1111,
+            rook_files: STANDARD_ROOK_FILES,
+        }
     }
 
     #[must_use]
     pub const fn empty() -> Castling {
-        Castling { inner: 0 }
+        Castling {
+            inner: 0,
+            rook_files: STANDARD_ROOK_FILES,
+        }
     }
 
     #[must_use]
     pub const fn has_castling_kind(&self, castling_kind: &CastlingKind) -> bool {
-        match castling_kind {
-            CastlingKind::WhiteKingside => self.inner & 0b1000 != 0,
-            CastlingKind::WhiteQueenside => self.inner & 0b0100 != 0,
-            CastlingKind::BlackKingside => self.inner & 0b0010 != 0,
-            CastlingKind::BlackQueenside => self.inner & 0b0001 != 0,
-        }
+        self.inner & castling_kind.mask() != 0
     }
 
     pub fn remove_color_castling(&mut self, color: &Color) {
@@ -156,21 +238,25 @@ impl Castling {
     }
 
     pub fn remove_castling_kind(&mut self, castling_kind: &CastlingKind) {
-        match castling_kind {
-            CastlingKind::WhiteKingside => self.inner &= 0b0111,
-            CastlingKind::WhiteQueenside => self.inner &= 0b1011,
-            CastlingKind::BlackKingside => self.inner &= 0b1101,
-            CastlingKind::BlackQueenside => self.inner &= 0b1110,
-        }
+        self.inner &= !castling_kind.mask();
     }
 
     pub fn add_castling_kind(&mut self, castling_kind: &CastlingKind) {
-        match castling_kind {
-            CastlingKind::WhiteKingside => self.inner |= 0b1000,
-            CastlingKind::WhiteQueenside => self.inner |= 0b0100,
-            CastlingKind::BlackKingside => self.inner |= 0b0010,
-            CastlingKind::BlackQueenside => self.inner |= 0b0001,
-        }
+        self.inner |= castling_kind.mask();
+    }
+
+    /// Grants the castling right and remembers the file of its rook.
+    pub fn set_castling_rook(&mut self, castling_kind: &CastlingKind, file: File) {
+        self.add_castling_kind(castling_kind);
+        self.rook_files[*castling_kind as usize] = file;
+    }
+
+    #[must_use]
+    pub const fn rook_square(&self, castling_kind: &CastlingKind) -> Square {
+        Square::from_file_rank(
+            self.rook_files[*castling_kind as usize],
+            castling_kind.back_rank(),
+        )
     }
 }
 
@@ -230,6 +316,13 @@ impl Position {
         Ok(())
     }
 
+    /// Like [`Position::add_piece_at`], for callers that know the square is empty.
+    #[inline(always)]
+    pub fn put_piece(&mut self, square: Square, piece: Piece, color: Color) {
+        self.pieces[color as usize][piece as usize] |= square.bitboard();
+        self.mailbox[square as usize] = Some((piece, color));
+    }
+
     #[must_use]
     #[inline(always)]
     pub const fn piece_at(&self, square: Square) -> Option<(Piece, Color)> {
@@ -252,6 +345,103 @@ impl Position {
         result
     }
 
+    /// Checks that the position could occur in a game and reports every problem found.
+    pub fn validate(&self) -> Result<(), Vec<PositionError>> {
+        let mut errors = Vec::new();
+
+        for color in Color::all() {
+            let pieces = &self.pieces[color as usize];
+            match pieces[Piece::King as usize].count() {
+                0 => errors.push(PositionError::MissingKing(color)),
+                1 => {}
+                _ => errors.push(PositionError::TooManyKings(color)),
+            }
+            if pieces[Piece::Pawn as usize].count() > 8 {
+                errors.push(PositionError::TooManyPawns(color));
+            }
+            if self.occupation(&color).count() > 16 {
+                errors.push(PositionError::TooManyPieces(color));
+            }
+        }
+
+        let pawns = self.pieces[Color::White as usize][Piece::Pawn as usize]
+            | self.pieces[Color::Black as usize][Piece::Pawn as usize];
+        for square in pawns & (Rank::R1.bitboard() | Rank::R8.bitboard()) {
+            errors.push(PositionError::PawnOnBackRank(square));
+        }
+
+        for kind in CastlingKind::all() {
+            if self.castling.has_castling_kind(&kind) && !self.castling_is_possible(kind) {
+                errors.push(PositionError::InvalidCastlingRights(kind));
+            }
+        }
+
+        if let Some(square) = self.en_passant {
+            if !self.en_passant_is_possible(square) {
+                errors.push(PositionError::InvalidEnPassant(square));
+            }
+        }
+
+        let enemy_king = self.pieces[self.enemy() as usize][Piece::King as usize];
+        if enemy_king.count() == 1 && self.is_square_attacked(enemy_king.lsb(), self.turn) {
+            errors.push(PositionError::OpponentInCheck);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn castling_is_possible(&self, castling_kind: CastlingKind) -> bool {
+        let color = castling_kind.color();
+        let king = self.pieces[color as usize][Piece::King as usize]
+            & castling_kind.back_rank().bitboard();
+        let rook = self.castling.rook_square(&castling_kind);
+        if king.count() != 1 || self.piece_at(rook) != Some((Piece::Rook, color)) {
+            return false;
+        }
+
+        (rook.file() > king.lsb().file()) == castling_kind.is_kingside()
+    }
+
+    fn en_passant_is_possible(&self, square: Square) -> bool {
+        // The pawn that has just moved belongs to the side not to move.
+        let (rank, forward) = match self.turn {
+            Color::White => (Rank::R6, 1),
+            Color::Black => (Rank::R3, -1),
+        };
+        if square.rank() != rank {
+            return false;
+        }
+
+        let (Some(pawn), Some(origin)) = (square.offset(-forward, 0), square.offset(forward, 0))
+        else {
+            return false;
+        };
+
+        self.piece_at(pawn) == Some((Piece::Pawn, self.enemy()))
+            && self.piece_at(square).is_none()
+            && self.piece_at(origin).is_none()
+    }
+
+    /// Slow attack detection that does not need the move generator lookup tables.
+    fn is_square_attacked(&self, square: Square, by: Color) -> bool {
+        let pieces = &self.pieces[by as usize];
+        let occupied = self.occupation(&Color::White) | self.occupation(&Color::Black);
+        let bb = square.bitboard();
+
+        let diagonal = pieces[Piece::Bishop as usize] | pieces[Piece::Queen as usize];
+        let straight = pieces[Piece::Rook as usize] | pieces[Piece::Queen as usize];
+
+        !(mask_pawns_attacks(bb, &by.enemy()) & pieces[Piece::Pawn as usize]).is_empty()
+            || !(mask_knights_attacks(bb) & pieces[Piece::Knight as usize]).is_empty()
+            || !(mask_king_attacks(bb) & pieces[Piece::King as usize]).is_empty()
+            || !(mask_slider_attacks_occ(Slider::Bishop, occupied, square) & diagonal).is_empty()
+            || !(mask_slider_attacks_occ(Slider::Rook, occupied, square) & straight).is_empty()
+    }
+
     #[must_use]
     pub fn semi_open_files(&self, color: &Color) -> Bitboard {
         let mut result = Bitboard::empty();
@@ -299,28 +489,6 @@ impl Display for Color {
     }
 }
 
-impl Display for Castling {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.has_castling_kind(&CastlingKind::WhiteKingside) {
-            write!(f, "K")?;
-        }
-
-        if self.has_castling_kind(&CastlingKind::WhiteQueenside) {
-            write!(f, "Q")?;
-        }
-
-        if self.has_castling_kind(&CastlingKind::BlackKingside) {
-            write!(f, "k")?;
-        }
-
-        if self.has_castling_kind(&CastlingKind::BlackQueenside) {
-            write!(f, "q")?;
-        }
-
-        Ok(())
-    }
-}
-
 impl Display for Piece {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -350,7 +518,7 @@ impl Display for Position {
         }
 
         writeln!(f, "Turn: {}", self.turn)?;
-        writeln!(f, "Castling: {}", self.castling)?;
+        writeln!(f, "Castling: {}", castling_to_fen(self, false))?;
         writeln!(f, "Hash: {}", self.hash)?;
         if let Some(en_passant) = self.en_passant {
             writeln!(f, "En passant: {en_passant}")?;
@@ -481,10 +649,11 @@ pub mod tests {
         }
     }
 
+    #[must_use]
     pub fn test_board(board: &[usize; 64]) -> Position {
         let mut position = Position::default();
         for (sq, &piece) in board.iter().enumerate() {
-            let sq = Square::from_u8((sq ^ 56) as u8);
+            let sq = Square::from_u8((sq ^ 0b11_1000) as u8);
             position.remove_piece_at(sq);
             if piece == 0 {
                 continue;
@@ -495,6 +664,10 @@ pub mod tests {
         }
 
         position.occupied = position.occupation(&Color::White) | position.occupation(&Color::Black);
+        // Adding and removing pieces doesn't update the keys.
+        position.hash = position.calc_hash();
+        position.pawn_hash = position.calc_pawn_hash();
+        position.material_key = position.calc_material_key();
 
         position
     }
@@ -2,7 +2,7 @@ use std::fmt::{Display, Formatter};
 
 use crate::bitboard::Bitboard;
 
-#[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Clone, Copy, Hash)]
 #[repr(u8)]
 pub enum File {
     A,
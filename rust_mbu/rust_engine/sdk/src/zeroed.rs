@@ -0,0 +1,122 @@
+use std::{
+    alloc::{alloc_zeroed, dealloc, handle_alloc_error, Layout},
+    ops::Deref,
+    ptr::NonNull,
+    sync::atomic::{AtomicI32, AtomicU64},
+};
+
+/// Types for which all bytes zero is a valid value.
+///
+/// # Safety
+/// Implementors must be valid when all of their bytes are zero.
+pub unsafe trait Zeroable {}
+
+unsafe impl Zeroable for AtomicU64 {}
+unsafe impl Zeroable for AtomicI32 {}
+unsafe impl<T: Zeroable, const N: usize> Zeroable for [T; N] {}
+unsafe impl<A: Zeroable, B: Zeroable> Zeroable for (A, B) {}
+
+/// Table of zeroed elements allocated at once. The memory comes zeroed from the system, so even
+/// tables of many gigabytes are ready right away.
+///
+/// `alloc_zeroed` only gets zeroed pages from the system for small alignments, above that it
+/// clears the memory itself. So the table is allocated with a small alignment and its start is
+/// aligned by hand, which keeps cache line aligned entries fast to allocate.
+pub struct ZeroedTable<T> {
+    data: NonNull<T>,
+    len: usize,
+    allocation: NonNull<u8>,
+    layout: Layout,
+}
+
+// SAFETY: The table owns its elements, like `Vec<T>` does.
+unsafe impl<T: Send> Send for ZeroedTable<T> {}
+unsafe impl<T: Sync> Sync for ZeroedTable<T> {}
+
+impl<T: Zeroable> ZeroedTable<T> {
+    #[must_use]
+    pub fn new(len: usize) -> Self {
+        assert!(
+            std::mem::size_of::<T>() > 0,
+            "Zero sized tables are not supported"
+        );
+
+        let size = std::mem::size_of::<T>()
+            .checked_mul(len.max(1))
+            .and_then(|size| size.checked_add(std::mem::align_of::<T>()))
+            .expect("Table is too large");
+        let layout =
+            Layout::from_size_align(size, std::mem::align_of::<u64>()).expect("Table is too large");
+
+        // SAFETY: The layout has a non-zero size.
+        let allocation = unsafe { alloc_zeroed(layout) };
+        let Some(allocation) = NonNull::new(allocation) else {
+            handle_alloc_error(layout);
+        };
+
+        // SAFETY: The offset is below `align_of::<T>()`, which was allocated in addition to the
+        // elements.
+        let data = unsafe {
+            allocation
+                .add(allocation.as_ptr().align_offset(std::mem::align_of::<T>()))
+                .cast::<T>()
+        };
+
+        ZeroedTable {
+            data,
+            len,
+            allocation,
+            layout,
+        }
+    }
+}
+
+impl<T> Deref for ZeroedTable<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        // SAFETY: `data` is aligned and points to `len` zeroed, and so valid, elements.
+        unsafe { std::slice::from_raw_parts(self.data.as_ptr(), self.len) }
+    }
+}
+
+impl<T> Drop for ZeroedTable<T> {
+    fn drop(&mut self) {
+        // SAFETY: The elements are valid and dropped only here, and the allocation was made with
+        // `layout`.
+        unsafe {
+            std::ptr::drop_in_place(std::ptr::slice_from_raw_parts_mut(
+                self.data.as_ptr(),
+                self.len,
+            ));
+            dealloc(self.allocation.as_ptr(), self.layout);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+
+    #[repr(align(64))]
+    struct Aligned([AtomicU64; 8]);
+
+    unsafe impl Zeroable for Aligned {}
+
+    #[test]
+    fn test_zeroed_table() {
+        let table = ZeroedTable::<(AtomicU64, [AtomicI32; 3])>::new(1000);
+        assert_eq!(table.len(), 1000);
+        assert!(table.iter().all(|(a, b)| a.load(Ordering::Relaxed) == 0
+            && b.iter().all(|b| b.load(Ordering::Relaxed) == 0)));
+        table[999].0.store(7, Ordering::Relaxed);
+        assert_eq!(table[999].0.load(Ordering::Relaxed), 7);
+
+        let aligned = ZeroedTable::<Aligned>::new(3);
+        assert_eq!(aligned.as_ptr() as usize % 64, 0);
+        assert_eq!(aligned[2].0[7].load(Ordering::Relaxed), 0);
+        assert!(ZeroedTable::<AtomicU64>::new(0).is_empty());
+    }
+}